@@ -0,0 +1,314 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+
+use parquet::basic::Compression;
+use parquet::data_type::{DoubleType, FloatType, Int32Type, Int64Type};
+use parquet::errors::Result;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use parquet::schema::types::SchemaDescriptor;
+
+use {Header, NtpTime, ObjectData, Scan};
+
+const ROW_GROUP_SIZE: usize = 1000000;
+
+const SCAN_POINTS_SCHEMA: &str = "
+message scan_points {
+    required int32 device_id (UINT_8);
+    required int32 scan_number (UINT_16);
+    required int64 scan_start_time_ntp (UINT_64);
+    required int64 scan_end_time_ntp (UINT_64);
+    required double timestamp;
+    required int32 layer (UINT_8);
+    required int32 echo (UINT_8);
+    required int32 flags (UINT_16);
+    required int32 horizontal_angle (INT_16);
+    required int32 radial_distance (UINT_16);
+    required int32 echo_pulse_width (UINT_16);
+    required float x;
+    required float y;
+    required float z;
+}
+";
+
+const OBJECTS_SCHEMA: &str = "
+message objects {
+    required int32 device_id (UINT_8);
+    required int64 scan_start_timestamp_ntp (UINT_64);
+    required double timestamp;
+    required int32 object_id (UINT_16);
+    required int32 object_age (UINT_16);
+    required int32 object_prediction_age (UINT_16);
+    required int32 relative_timestamp (UINT_16);
+    required int32 reference_point_x (INT_16);
+    required int32 reference_point_y (INT_16);
+    required int32 reference_point_sigma_x (INT_16);
+    required int32 reference_point_sigma_y (INT_16);
+    required int32 closest_point_x (INT_16);
+    required int32 closest_point_y (INT_16);
+    required int32 bounding_box_center_x (INT_16);
+    required int32 bounding_box_center_y (INT_16);
+    required int32 bounding_box_size_x (UINT_16);
+    required int32 bounding_box_size_y (UINT_16);
+    required int32 object_box_center_x (INT_16);
+    required int32 object_box_center_y (INT_16);
+    required int32 object_box_size_x (UINT_16);
+    required int32 object_box_size_y (UINT_16);
+    required int32 object_box_orientation (INT_16);
+    required int32 absolute_velocity_x (INT_16);
+    required int32 absolute_velocity_y (INT_16);
+    required int32 absolute_velocity_sigma_x (UINT_16);
+    required int32 absolute_velocity_sigma_y (UINT_16);
+    required int32 relative_velocity_x (INT_16);
+    required int32 relative_velocity_y (INT_16);
+    required int32 number_of_contour_points (UINT_16);
+}
+";
+
+enum Value {
+    Int32(i32),
+    Int64(i64),
+    Float(f32),
+    Double(f64),
+}
+
+enum Column {
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+// buffers rows column by column and writes them out in row groups
+struct Table {
+    writer: SerializedFileWriter<BufWriter<File>>,
+    columns: Vec<Column>,
+    rows: usize,
+}
+
+impl Table {
+    fn create(path: &Path, message_type: &str) -> Result<Table> {
+        let schema = Arc::new(parse_message_type(message_type)?);
+        let columns = SchemaDescriptor::new(schema.clone())
+            .columns()
+            .iter()
+            .map(|column| match column.physical_type() {
+                ::parquet::basic::Type::INT64 => Column::Int64(Vec::new()),
+                ::parquet::basic::Type::FLOAT => Column::Float(Vec::new()),
+                ::parquet::basic::Type::DOUBLE => Column::Double(Vec::new()),
+                _ => Column::Int32(Vec::new()),
+            })
+            .collect();
+        let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Table {
+            writer: SerializedFileWriter::new(file, schema, properties)?,
+            columns,
+            rows: 0,
+        })
+    }
+
+    fn push(&mut self, row: &[Value]) -> Result<()> {
+        for (column, value) in self.columns.iter_mut().zip(row.iter()) {
+            match (column, value) {
+                (&mut Column::Int32(ref mut values), &Value::Int32(v)) => values.push(v),
+                (&mut Column::Int64(ref mut values), &Value::Int64(v)) => values.push(v),
+                (&mut Column::Float(ref mut values), &Value::Float(v)) => values.push(v),
+                (&mut Column::Double(ref mut values), &Value::Double(v)) => values.push(v),
+                _ => panic!("value does not match the column type"),
+            }
+        }
+        self.rows += 1;
+        if self.rows >= ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut row_group = self.writer.next_row_group()?;
+        for column in self.columns.iter_mut() {
+            let mut column_writer = row_group.next_column()?.unwrap();
+            match *column {
+                Column::Int32(ref mut values) => {
+                    column_writer.typed::<Int32Type>().write_batch(values, None, None)?;
+                    values.clear();
+                },
+                Column::Int64(ref mut values) => {
+                    column_writer.typed::<Int64Type>().write_batch(values, None, None)?;
+                    values.clear();
+                },
+                Column::Float(ref mut values) => {
+                    column_writer.typed::<FloatType>().write_batch(values, None, None)?;
+                    values.clear();
+                },
+                Column::Double(ref mut values) => {
+                    column_writer.typed::<DoubleType>().write_batch(values, None, None)?;
+                    values.clear();
+                },
+            }
+            column_writer.close()?;
+        }
+        row_group.close()?;
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.write_row_group()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+fn ntp(time: &NtpTime) -> i64 {
+    (((time.secs as u64) << 32) | (time.precise as u64)) as i64
+}
+
+pub struct ParquetWriter {
+    scan_points: Table,
+    objects: Table,
+}
+
+impl ParquetWriter {
+    pub fn create(dir: &Path) -> Result<ParquetWriter> {
+        Ok(ParquetWriter {
+            scan_points: Table::create(&dir.join("scan_points.parquet"), SCAN_POINTS_SCHEMA)?,
+            objects: Table::create(&dir.join("objects.parquet"), OBJECTS_SCHEMA)?,
+        })
+    }
+
+    pub fn write_scan(&mut self, header: &Header, scan: &Scan) -> Result<()> {
+        for p in &scan.points {
+            self.scan_points.push(&[
+                Value::Int32(p.device_id.unwrap_or(header.device_id) as i32),
+                Value::Int32(scan.scan_number as i32),
+                Value::Int64(ntp(&scan.start_time)),
+                Value::Int64(ntp(&scan.end_time)),
+                Value::Double(p.time),
+                Value::Int32(p.layer as i32),
+                Value::Int32(p.echo as i32),
+                Value::Int32(p.flags as i32),
+                // in the units of the lux, 1/32 deg and cm
+                Value::Int32((p.angle.to_degrees() * 32.0).round() as i32),
+                Value::Int32((p.distance * 100.0).round() as i32),
+                Value::Int32(p.echo_pulse_width as i32),
+                Value::Float(p.x),
+                Value::Float(p.y),
+                Value::Float(p.z),
+            ])?;
+        }
+        Ok(())
+    }
+
+    pub fn write_objects(&mut self, header: &Header, object_data: &ObjectData) -> Result<()> {
+        let timestamp = object_data.scan_start_timestamp.unix_timestamp();
+        for o in &object_data.objects_vec {
+            self.objects.push(&[
+                Value::Int32(header.device_id as i32),
+                Value::Int64(ntp(&object_data.scan_start_timestamp)),
+                Value::Double(timestamp),
+                Value::Int32(o.object_id as i32),
+                Value::Int32(o.object_age as i32),
+                Value::Int32(o.object_prediction_age as i32),
+                Value::Int32(o.relative_timestamp as i32),
+                Value::Int32(o.reference_point.position_x as i32),
+                Value::Int32(o.reference_point.position_y as i32),
+                Value::Int32(o.reference_point_sigma.position_x as i32),
+                Value::Int32(o.reference_point_sigma.position_y as i32),
+                Value::Int32(o.closest_point.position_x as i32),
+                Value::Int32(o.closest_point.position_y as i32),
+                Value::Int32(o.bounding_box_center.position_x as i32),
+                Value::Int32(o.bounding_box_center.position_y as i32),
+                Value::Int32(o.bounding_box_size.size_x as i32),
+                Value::Int32(o.bounding_box_size.size_y as i32),
+                Value::Int32(o.object_box_center.position_x as i32),
+                Value::Int32(o.object_box_center.position_y as i32),
+                Value::Int32(o.object_box_size.size_x as i32),
+                Value::Int32(o.object_box_size.size_y as i32),
+                Value::Int32(o.object_box_orientation as i32),
+                Value::Int32(o.absolute_velocity.position_x as i32),
+                Value::Int32(o.absolute_velocity.position_y as i32),
+                Value::Int32(o.absolute_velocity_sigma.size_x as i32),
+                Value::Int32(o.absolute_velocity_sigma.size_y as i32),
+                Value::Int32(o.relative_velocity.position_x as i32),
+                Value::Int32(o.relative_velocity.position_y as i32),
+                Value::Int32(o.number_of_contour_points as i32),
+            ])?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.scan_points.finish()?;
+        self.objects.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    use super::ParquetWriter;
+    use fixtures::{object_data, point, record, scan, scan_data, temp_path};
+    use Point;
+
+    fn reader(path: &Path) -> SerializedFileReader<File> {
+        SerializedFileReader::new(File::open(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn tables() {
+        let dir = temp_path("parquet");
+        fs::create_dir_all(&dir).unwrap();
+        let scan_record = record(0x2202, &scan_data(0.0));
+        let object_record = record(0x2221, &object_data(&[7, 8, 9]));
+        let mut writer = ParquetWriter::create(&dir).unwrap();
+        let points = vec![
+            point(1.0, 2.0, 3.0),
+            Point {
+                layer: 3,
+                echo_pulse_width: 250,
+                device_id: Some(4),
+                ..point(0.0, 10.0, 0.0)
+            },
+        ];
+        writer.write_scan(&scan_record.header, &scan(points)).unwrap();
+        writer.write_objects(&object_record.header, &object_record.message.object_data().unwrap()).unwrap();
+        writer.finish().unwrap();
+
+        let scan_points = reader(&dir.join("scan_points.parquet"));
+        let objects = reader(&dir.join("objects.parquet"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let metadata = scan_points.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        assert_eq!(metadata.schema_descr().num_columns(), 14);
+        let rows: Vec<_> = scan_points.get_row_iter(None).unwrap().map(Result::unwrap).collect();
+        assert_eq!(rows[0].get_ubyte(0).unwrap(), 1);
+        assert_eq!(rows[1].get_ubyte(0).unwrap(), 4);
+        assert_eq!(rows[1].get_ubyte(5).unwrap(), 3);
+        // a quarter turn in 1/32 deg and 10 m in cm
+        assert_eq!(rows[1].get_short(8).unwrap(), 90 * 32);
+        assert_eq!(rows[1].get_ushort(9).unwrap(), 1000);
+        assert_eq!(rows[1].get_ushort(10).unwrap(), 250);
+        assert_eq!(rows[1].get_float(12).unwrap(), 10.0);
+
+        let metadata = objects.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 3);
+        assert_eq!(metadata.schema_descr().num_columns(), 29);
+        let ids: Vec<u16> =
+            objects.get_row_iter(None).unwrap().map(|row| row.unwrap().get_ushort(3).unwrap()).collect();
+        assert_eq!(ids, vec![7, 8, 9]);
+    }
+}
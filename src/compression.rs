@@ -0,0 +1,319 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use flate2;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use zstd;
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+// smallest chunk closed by a periodic flush
+const MIN_CHUNK_SIZE: usize = 256 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a5e;
+const SEEKABLE_MAGIC: u32 = 0x8f92_eab1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path(path: &Path) -> Option<Compression> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Some(Compression::Gzip),
+            Some("zst") => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+// "drive.ndjson.zst" -> "drive.ndjson", so the inner extension picks the format
+pub fn strip_extension(path: &Path) -> PathBuf {
+    match Compression::from_path(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    }
+}
+
+// every chunk is an independent zstd frame or gzip member, so a reader can
+// start decoding at any chunk boundary. zstd files end with a seek table in
+// the zstd seekable format, which plain decoders skip as a skippable frame.
+pub struct ChunkEncoder<W: Write> {
+    writer: W,
+    compression: Compression,
+    chunk: Vec<u8>,
+    seek_table: Vec<(u32, u32)>,
+}
+
+impl<W: Write> ChunkEncoder<W> {
+    pub fn new(writer: W, compression: Compression) -> ChunkEncoder<W> {
+        ChunkEncoder {
+            writer,
+            compression,
+            chunk: Vec::new(),
+            seek_table: Vec::new(),
+        }
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let compressed = match self.compression {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&self.chunk)?;
+                encoder.finish()?
+            },
+            Compression::Zstd => zstd::bulk::compress(&self.chunk, ZSTD_LEVEL)?,
+        };
+        self.writer.write_all(&compressed)?;
+        self.seek_table.push((compressed.len() as u32, self.chunk.len() as u32));
+        self.chunk.clear();
+        Ok(())
+    }
+
+    fn write_seek_table(&mut self) -> io::Result<()> {
+        let mut frame = Vec::new();
+        frame.write_u32::<LittleEndian>(SKIPPABLE_MAGIC)?;
+        frame.write_u32::<LittleEndian>(self.seek_table.len() as u32 * 8 + 9)?;
+        for &(compressed_size, size) in &self.seek_table {
+            frame.write_u32::<LittleEndian>(compressed_size)?;
+            frame.write_u32::<LittleEndian>(size)?;
+        }
+        frame.write_u32::<LittleEndian>(self.seek_table.len() as u32)?;
+        frame.write_u8(0)?;
+        frame.write_u32::<LittleEndian>(SEEKABLE_MAGIC)?;
+        self.writer.write_all(&frame)
+    }
+
+    // closes the current chunk only once it compresses well, so a flush
+    // every second does not leave thousands of tiny frames. what was written
+    // since is not readable until the next chunk is closed.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.chunk.len() >= MIN_CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        self.writer.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk()?;
+        if self.compression == Compression::Zstd {
+            self.write_seek_table()?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ChunkEncoder<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buffer);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(buffer.len())
+    }
+
+    // closes the current chunk so everything written so far can be decoded
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.writer.flush()
+    }
+}
+
+pub enum OutputFile {
+    Plain(BufWriter<File>),
+    Compressed(ChunkEncoder<BufWriter<File>>),
+}
+
+impl OutputFile {
+    // compression is chosen by the extension of the path
+    pub fn create(path: &Path) -> io::Result<OutputFile> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match Compression::from_path(path) {
+            Some(compression) => OutputFile::Compressed(ChunkEncoder::new(file, compression)),
+            None => OutputFile::Plain(file),
+        })
+    }
+
+    pub fn sync(&mut self, fsync: bool) -> io::Result<()> {
+        match *self {
+            OutputFile::Plain(ref mut file) => file.flush()?,
+            OutputFile::Compressed(ref mut encoder) => encoder.sync()?,
+        }
+        if fsync {
+            let file = match *self {
+                OutputFile::Plain(ref file) => file.get_ref(),
+                OutputFile::Compressed(ref encoder) => encoder.get_ref().get_ref(),
+            };
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            OutputFile::Plain(mut file) => file.flush(),
+            OutputFile::Compressed(encoder) => encoder.finish().map(|_| ()),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match *self {
+            OutputFile::Plain(ref mut file) => file.write(buffer),
+            OutputFile::Compressed(ref mut encoder) => encoder.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            OutputFile::Plain(ref mut file) => file.flush(),
+            OutputFile::Compressed(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+fn detect(file: &mut BufReader<File>) -> io::Result<Option<Compression>> {
+    let magic = file.fill_buf()?;
+    if magic.starts_with(ZSTD_MAGIC) {
+        Ok(Some(Compression::Zstd))
+    } else if magic.starts_with(GZIP_MAGIC) {
+        Ok(Some(Compression::Gzip))
+    } else {
+        Ok(None)
+    }
+}
+
+// compression is detected from the magic bytes, not the file name
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut file = BufReader::new(File::open(path)?);
+    match detect(&mut file)? {
+        Some(Compression::Zstd) => Ok(Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(file)?))),
+        Some(Compression::Gzip) => Ok(Box::new(BufReader::new(MultiGzDecoder::new(file)))),
+        None => Ok(Box::new(file)),
+    }
+}
+
+// whether the file ends with the magic of a seek table
+fn has_seek_table(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() < 4 {
+        return Ok(false);
+    }
+    let mut tail = [0; 4];
+    file.seek(SeekFrom::End(-4))?;
+    file.read_exact(&mut tail)?;
+    Ok(LittleEndian::read_u32(&tail) == SEEKABLE_MAGIC)
+}
+
+// reads as much as can be decompressed from a possibly truncated file. the
+// flag tells whether the file was closed properly.
+pub fn read_recoverable(path: &Path, data: &mut Vec<u8>) -> io::Result<(Option<Compression>, bool)> {
+    let mut file = BufReader::new(File::open(path)?);
+    let compression = detect(&mut file)?;
+    let complete = match compression {
+        Some(Compression::Zstd) => {
+            let complete = zstd::stream::read::Decoder::with_buffer(file)?.read_to_end(data).is_ok();
+            complete && has_seek_table(path)?
+        },
+        Some(Compression::Gzip) => MultiGzDecoder::new(file).read_to_end(data).is_ok(),
+        None => file.read_to_end(data).map(|_| true)?,
+    };
+    Ok((compression, complete))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Read, Write};
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::{open, read_recoverable, ChunkEncoder, Compression, MIN_CHUNK_SIZE, SEEKABLE_MAGIC, ZSTD_MAGIC};
+    use fixtures::temp_path;
+
+    // three chunks, closed by the flushes and `finish`
+    fn round_trip(name: &str, compression: Compression) -> (Vec<u8>, usize) {
+        let mut encoder = ChunkEncoder::new(Vec::new(), compression);
+        let mut data = Vec::new();
+        for chunk in 0..3 {
+            for line in 0..1000 {
+                let text = format!("chunk {} line {}\n", chunk, line);
+                encoder.write_all(text.as_bytes()).unwrap();
+                data.extend_from_slice(text.as_bytes());
+            }
+            if chunk < 2 {
+                encoder.flush().unwrap();
+            }
+        }
+        let compressed = encoder.finish().unwrap();
+
+        let path = temp_path(name);
+        fs::write(&path, &compressed).unwrap();
+        let mut read = Vec::new();
+        open(&path).unwrap().read_to_end(&mut read).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read, data);
+        (compressed, data.len())
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        let (compressed, data_len) = round_trip("round_trip.zst", Compression::Zstd);
+        // seek table footer: number of frames, descriptor, magic
+        let len = compressed.len();
+        assert_eq!(LittleEndian::read_u32(&compressed[len - 4..]), SEEKABLE_MAGIC);
+        assert_eq!(compressed[len - 5], 0);
+        let frames = LittleEndian::read_u32(&compressed[len - 9..len - 5]) as usize;
+        assert_eq!(frames, 3);
+        // the entries add up to the frames before the table
+        let table = &compressed[len - 9 - frames * 8..len - 9];
+        let compressed_size: usize = table.chunks(8).map(|entry| LittleEndian::read_u32(&entry[0..4]) as usize).sum();
+        let size: usize = table.chunks(8).map(|entry| LittleEndian::read_u32(&entry[4..8]) as usize).sum();
+        assert_eq!(compressed_size, len - 9 - frames * 8 - 8);
+        assert_eq!(size, data_len);
+    }
+
+    #[test]
+    fn sync_keeps_small_chunks_open() {
+        let mut encoder = ChunkEncoder::new(Vec::new(), Compression::Zstd);
+        encoder.write_all(&[1; 1000]).unwrap();
+        encoder.sync().unwrap();
+        assert!(encoder.get_ref().is_empty());
+        encoder.write_all(&vec![2; MIN_CHUNK_SIZE]).unwrap();
+        encoder.sync().unwrap();
+        assert_eq!(encoder.seek_table, vec![(encoder.get_ref().len() as u32, MIN_CHUNK_SIZE as u32 + 1000)]);
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        round_trip("round_trip.gz", Compression::Gzip);
+    }
+
+    #[test]
+    fn truncated_zstd() {
+        let path = temp_path("truncated.zst");
+        // nothing but the magic of the first frame
+        fs::write(&path, ZSTD_MAGIC).unwrap();
+        let mut data = Vec::new();
+        let recovered = read_recoverable(&path, &mut data);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recovered.unwrap(), (Some(Compression::Zstd), false));
+        assert!(data.is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::prelude::*;
+use serde::de::{Deserialize, Deserializer, Error};
+use serde_path_to_error;
+use serde_yaml::{self, Value};
+
+use manifest::MountingPose;
+use rotate::Rotation;
+use select::{self, Selection};
+use window;
+
+// a recording profile, e.g.
+//
+//     sensors:
+//       - name: front
+//         address: 192.168.0.1:12002
+//         mounting_pose: 1.2,0,1.5,0,0,0
+//       - name: rear
+//         address: 192.168.0.2:12002
+//         mounting_pose: -3.8,0,1.5,180,0,0
+//     output:
+//       path: drive.ndjson.zst
+//       record: scan:mcap,object,ego
+//       per_sensor: true
+//     rotation:
+//       minutes: 10
+//     time_sync:
+//       interval: 60
+//     filters:
+//       start_objects: 1
+//       max_bytes: 20G
+//     foxglove:
+//       listen: 0.0.0.0:8765
+//
+// options given on the command line override the profile
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub sensors: Vec<Sensor>,
+    pub output: Output,
+    pub rotation: RotationConfig,
+    pub time_sync: TimeSync,
+    pub filters: Filters,
+    pub foxglove: FoxgloveConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sensor {
+    // "sensor1", "sensor2", ... in the order of the list without it
+    #[serde(default)]
+    pub name: String,
+    pub address: String,
+    #[serde(default, deserialize_with = "mounting_pose")]
+    pub mounting_pose: Option<MountingPose>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Output {
+    pub path: Option<String>,
+    #[serde(deserialize_with = "selections")]
+    pub record: Option<Vec<Selection>>,
+    pub flush_interval: Option<f64>,
+    pub fsync: bool,
+    pub stop_measure: bool,
+    // a file for each sensor instead of a common one
+    pub per_sensor: bool,
+    // data labelled by device id, for the scanners behind a fusion ecu
+    pub split_devices: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RotationConfig {
+    #[serde(rename = "size_mb", deserialize_with = "megabytes")]
+    pub max_bytes: Option<u64>,
+    pub minutes: Option<f64>,
+    pub scans: Option<u64>,
+}
+
+impl RotationConfig {
+    pub fn rotation(&self) -> Rotation {
+        Rotation {
+            max_bytes: self.max_bytes,
+            max_seconds: self.minutes.map(|minutes| minutes * 60.0),
+            max_scans: self.scans,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeSync {
+    // seconds between two time syncs, only once at the start without it
+    pub interval: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filters {
+    #[serde(deserialize_with = "time")]
+    pub start_at: Option<DateTime<Utc>>,
+    pub start_objects: Option<u16>,
+    pub duration: Option<f64>,
+    pub max_scans: Option<u64>,
+    #[serde(deserialize_with = "bytes")]
+    pub max_bytes: Option<u64>,
+    #[serde(deserialize_with = "time")]
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoxgloveConfig {
+    // address of the foxglove websocket server, no server without it
+    pub listen: Option<String>,
+}
+
+fn mounting_pose<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<MountingPose>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    MountingPose::parse(&text)
+        .map(Some)
+        .ok_or_else(|| D::Error::custom(format!("invalid mounting pose '{}', expected X,Y,Z,YAW,PITCH,ROLL", text)))
+}
+
+fn selections<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Selection>>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    select::parse(&text).map(Some).map_err(D::Error::custom)
+}
+
+fn time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    window::parse_time(&text)
+        .map(Some)
+        .ok_or_else(|| D::Error::custom(format!("invalid time '{}', expected rfc 3339 or HH:MM[:SS]", text)))
+}
+
+// a plain number of bytes or a size like "200M"
+fn bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let size = match Value::deserialize(deserializer)? {
+        Value::Number(number) => number.as_u64(),
+        Value::String(text) => window::parse_bytes(&text),
+        _ => None,
+    };
+    size.map(Some).ok_or_else(|| D::Error::custom("invalid size, expected bytes or a size like 200M"))
+}
+
+// a number of megabytes in bytes
+fn megabytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let size = u64::deserialize(deserializer)?;
+    size.checked_mul(1024 * 1024)
+        .map(Some)
+        .ok_or_else(|| D::Error::custom(format!("invalid size of {} MB", size)))
+}
+
+impl Config {
+    // errors name the key they are about, e.g. "filters.until: invalid time"
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        if text.trim().is_empty() {
+            return Ok(Config::default());
+        }
+        let value: Value = serde_yaml::from_str(&text).map_err(|e| e.to_string())?;
+        let value = match value {
+            Value::Null => return Ok(Config::default()),
+            value => value,
+        };
+        serde_path_to_error::deserialize(value).map_err(|e| match e.path().to_string().as_str() {
+            "." => e.into_inner().to_string(),
+            path => format!("{}: {}", path, e.into_inner()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Config;
+    use fixtures::temp_path;
+
+    fn load(name: &str, text: &str) -> Result<Config, String> {
+        let path = temp_path(name);
+        fs::write(&path, text).unwrap();
+        let config = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn profile() {
+        let text = "
+sensors:
+  - name: front
+    address: 192.168.0.1:12002
+    mounting_pose: 1.2,0,1.5,0,0,0
+  - address: 192.168.0.2:12002
+output:
+  path: drive.ndjson.zst
+  record: scan:mcap,object,ego
+  per_sensor: true
+rotation:
+  size_mb: 512
+  minutes: 10
+filters:
+  start_objects: 1
+  max_bytes: 20G
+foxglove:
+  listen: 0.0.0.0:8765
+";
+        let config = load("profile.yaml", text).unwrap();
+        assert_eq!(config.sensors.len(), 2);
+        assert_eq!(config.sensors[0].name, "front");
+        assert!(config.sensors[0].mounting_pose.is_some());
+        assert_eq!(config.sensors[1].name, "");
+        assert_eq!(config.output.path.as_deref(), Some("drive.ndjson.zst"));
+        assert_eq!(config.output.record.unwrap().len(), 3);
+        assert!(config.output.per_sensor);
+        let rotation = config.rotation.rotation();
+        assert_eq!(rotation.max_bytes, Some(512 * 1024 * 1024));
+        assert_eq!(rotation.max_seconds, Some(600.0));
+        assert_eq!(config.filters.max_bytes, Some(20 * 1024 * 1024 * 1024));
+        assert_eq!(config.foxglove.listen.as_deref(), Some("0.0.0.0:8765"));
+    }
+
+    #[test]
+    fn empty() {
+        for &(name, text) in [("empty.yaml", ""), ("null.yaml", "~\n")].iter() {
+            let config = load(name, text).unwrap();
+            assert!(config.sensors.is_empty());
+            assert!(config.output.path.is_none());
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = load("unknown.yaml", "output:\n  compression: zstd\n").unwrap_err();
+        assert!(error.starts_with("output.compression: unknown field `compression`"), "{}", error);
+        let error = load("size.yaml", "rotation:\n  size_mb: 99999999999999999\n").unwrap_err();
+        assert_eq!(error, "rotation.size_mb: invalid size of 99999999999999999 MB");
+        let error = load("pose.yaml", "sensors:\n  - address: a:1\n    mounting_pose: 1,2,3\n").unwrap_err();
+        assert!(error.starts_with("sensors[0].mounting_pose: invalid mounting pose '1,2,3'"), "{}", error);
+        let error = load("record.yaml", "output:\n  record: scan:bag.zst\n").unwrap_err();
+        assert!(error.starts_with("output.record: bag files cannot be compressed"), "{}", error);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use {Header, ObjectData};
+
+// one row per object per frame, in the units of the object data
+pub struct ObjectCsvWriter {
+    file: BufWriter<File>,
+}
+
+impl ObjectCsvWriter {
+    pub fn create(path: &Path) -> io::Result<ObjectCsvWriter> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "device_id,timestamp,object_id,object_age,object_prediction_age,relative_timestamp,\
+             reference_point_x,reference_point_y,reference_point_sigma_x,reference_point_sigma_y,\
+             closest_point_x,closest_point_y,\
+             bounding_box_center_x,bounding_box_center_y,bounding_box_size_x,bounding_box_size_y,\
+             object_box_center_x,object_box_center_y,object_box_size_x,object_box_size_y,object_box_orientation,\
+             absolute_velocity_x,absolute_velocity_y,absolute_velocity_sigma_x,absolute_velocity_sigma_y,\
+             relative_velocity_x,relative_velocity_y,number_of_contour_points"
+        )?;
+        Ok(ObjectCsvWriter { file })
+    }
+
+    pub fn write_objects(&mut self, header: &Header, object_data: &ObjectData) -> io::Result<()> {
+        let timestamp = object_data.scan_start_timestamp.unix_timestamp();
+        for o in &object_data.objects_vec {
+            writeln!(
+                self.file,
+                "{},{:.6},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                header.device_id,
+                timestamp,
+                o.object_id,
+                o.object_age,
+                o.object_prediction_age,
+                o.relative_timestamp,
+                o.reference_point.position_x,
+                o.reference_point.position_y,
+                o.reference_point_sigma.position_x,
+                o.reference_point_sigma.position_y,
+                o.closest_point.position_x,
+                o.closest_point.position_y,
+                o.bounding_box_center.position_x,
+                o.bounding_box_center.position_y,
+                o.bounding_box_size.size_x,
+                o.bounding_box_size.size_y,
+                o.object_box_center.position_x,
+                o.object_box_center.position_y,
+                o.object_box_size.size_x,
+                o.object_box_size.size_y,
+                o.object_box_orientation,
+                o.absolute_velocity.position_x,
+                o.absolute_velocity.position_y,
+                o.absolute_velocity_sigma.size_x,
+                o.absolute_velocity_sigma.size_y,
+                o.relative_velocity.position_x,
+                o.relative_velocity.position_y,
+                o.number_of_contour_points
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::ObjectCsvWriter;
+    use fixtures::{object_data, record, temp_path};
+
+    #[test]
+    fn columns() {
+        let path = temp_path("objects.csv");
+        let record = record(0x2221, &object_data(&[7, 8]));
+        let mut writer = ObjectCsvWriter::create(&path).unwrap();
+        writer.write_objects(&record.header, &record.message.object_data().unwrap()).unwrap();
+        writer.flush().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let rows: Vec<Vec<&str>> = text.lines().map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), 3);
+        let columns = &rows[0];
+        assert_eq!(columns.len(), 28);
+        assert_eq!(columns[..3], ["device_id", "timestamp", "object_id"]);
+        assert_eq!(columns.last(), Some(&"number_of_contour_points"));
+        for row in &rows[1..] {
+            assert_eq!(row.len(), columns.len());
+        }
+        assert_eq!(rows[1][0], "1");
+        assert_eq!((rows[1][2], rows[2][2]), ("7", "8"));
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::time::Instant;
+
+use chrono::prelude::*;
+
+use config::Sensor;
+use stream::{Message, Record};
+
+const REFRESH_SECONDS: f64 = 1.0;
+const LOG_SECONDS: f64 = 10.0;
+// weight of the newest scan period in the scan frequency
+const SMOOTHING: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    // redrawn in place on a terminal
+    Live,
+    // a status line every LOG_SECONDS when the output is redirected
+    Log,
+    Quiet,
+}
+
+// levels of a ros diagnostic status
+pub const OK: u8 = 0;
+pub const WARN: u8 = 1;
+pub const ERROR: u8 = 2;
+pub const STALE: u8 = 3;
+
+// the health of one sensor, published as a diagnostic status
+pub struct Health {
+    pub name: String,
+    pub address: String,
+    pub level: u8,
+    pub message: String,
+    pub values: Vec<(&'static str, String)>,
+}
+
+struct SensorStatus {
+    name: String,
+    address: String,
+    device_id: Option<u8>,
+    // why the connection was closed
+    closed: Option<String>,
+    counts: BTreeMap<String, u64>,
+    rates: BTreeMap<String, f64>,
+    last_scan_start: Option<f64>,
+    scan_frequency: Option<f64>,
+    points: Option<usize>,
+    objects: Option<usize>,
+    // seconds the sensor clock is ahead of this computer, including the
+    // transmission delay
+    time_offset: Option<f64>,
+    last_error: Option<String>,
+    // from the registers of the latest error data
+    level: u8,
+    // when split by device, e.g. behind a fusion ecu
+    devices: BTreeMap<u8, SensorStatus>,
+}
+
+impl SensorStatus {
+    fn new(name: &str, address: &str) -> SensorStatus {
+        SensorStatus {
+            name: name.to_string(),
+            address: address.to_string(),
+            device_id: None,
+            closed: None,
+            counts: BTreeMap::new(),
+            rates: BTreeMap::new(),
+            last_scan_start: None,
+            scan_frequency: None,
+            points: None,
+            objects: None,
+            time_offset: None,
+            last_error: None,
+            level: OK,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, record: &Record) {
+        *self.counts.entry(format!("{:?}", record.data_type)).or_insert(0) += 1;
+        let now = Utc::now();
+        let now = now.timestamp() as f64 + now.timestamp_subsec_nanos() as f64 / 1e9;
+        self.time_offset = Some(record.timestamp - now);
+        if let Some(scan_start_time) = record.message.scan_start_time() {
+            let scan_start = scan_start_time.unix_timestamp();
+            if let Some(last_scan_start) = self.last_scan_start {
+                if scan_start > last_scan_start {
+                    let frequency = 1.0 / (scan_start - last_scan_start);
+                    self.scan_frequency = Some(match self.scan_frequency {
+                        Some(smoothed) => smoothed + SMOOTHING * (frequency - smoothed),
+                        None => frequency,
+                    });
+                }
+            }
+            self.last_scan_start = Some(scan_start);
+            self.points = record.message.point_count();
+        }
+        match record.message {
+            Message::ObjectData(ref object_data) => {
+                self.objects = Some(object_data.objects_vec.len());
+            },
+            Message::FusedObjectData(ref object_data) => {
+                self.objects = Some(object_data.objects_vec.len());
+            },
+            Message::Error(ref error) => {
+                let registers = [
+                    ("error_register_1", error.error_register_1),
+                    ("error_register_2", error.error_register_2),
+                    ("warning_register_1", error.warning_register_1),
+                    ("warning_register_2", error.warning_register_2),
+                ];
+                let set: Vec<String> = registers
+                    .iter()
+                    .filter(|&&(_, value)| value != 0)
+                    .map(|&(name, value)| format!("{}=0x{:04x}", name, value))
+                    .collect();
+                self.level = if error.error_register_1 != 0 || error.error_register_2 != 0 {
+                    ERROR
+                } else if error.warning_register_1 != 0 || error.warning_register_2 != 0 {
+                    WARN
+                } else {
+                    OK
+                };
+                if !set.is_empty() {
+                    let time = record.header.ntp_time.datetime().with_timezone(&Local);
+                    self.last_error = Some(format!("{} at {}", set.join(" "), time.format("%H:%M:%S")));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn refresh(&mut self, elapsed: f64) {
+        let mut rates: BTreeMap<String, f64> = self.rates.keys().map(|data_type| (data_type.clone(), 0.0)).collect();
+        for (data_type, &count) in &self.counts {
+            rates.insert(data_type.clone(), count as f64 / elapsed);
+        }
+        self.rates = rates;
+        self.counts.clear();
+        for device in self.devices.values_mut() {
+            device.refresh(elapsed);
+        }
+    }
+
+    fn connection(&self) -> String {
+        let device = self.device_id.map_or(String::new(), |device_id| format!(", device {}", device_id));
+        let state = self.closed.as_ref().map_or("connected", String::as_str);
+        format!("{}{}, {}", self.address, device, state)
+    }
+
+    fn values(&self) -> Vec<(&'static str, String)> {
+        let rates: Vec<String> = self.rates.iter().map(|(data_type, rate)| format!("{} {:.1}", data_type, rate)).collect();
+        let scans = match (self.scan_frequency, self.points) {
+            (Some(frequency), Some(points)) => format!("{:.2} Hz, {} points per scan", frequency, points),
+            (None, Some(points)) => format!("{} points per scan", points),
+            _ => "-".to_string(),
+        };
+        vec![
+            ("messages/s", if rates.is_empty() { "-".to_string() } else { rates.join("  ") }),
+            ("scans", scans),
+            ("objects", self.objects.map_or("-".to_string(), |objects| objects.to_string())),
+            ("time offset", self.time_offset.map_or("-".to_string(), |offset| format!("{:+.1} ms", offset * 1000.0))),
+            ("last error", self.last_error.clone().unwrap_or_else(|| "none".to_string())),
+        ]
+    }
+
+    fn group(&self, label: &str, indent: &str) -> Vec<(String, String)> {
+        let mut group = vec![(label.to_string(), self.connection())];
+        group.extend(self.values().into_iter().map(|(name, value)| (format!("{}{}", indent, name), value)));
+        group
+    }
+
+    fn health(&self, state: &str) -> Health {
+        let (level, message) = if let Some(ref closed) = self.closed {
+            (STALE, closed.clone())
+        } else if self.rates.values().all(|&rate| rate == 0.0) {
+            (STALE, "no messages from the sensor".to_string())
+        } else if self.level == OK {
+            (OK, state.to_string())
+        } else {
+            (self.level, self.last_error.clone().unwrap_or_default())
+        };
+        let mut values = vec![("connection", self.connection())];
+        values.extend(self.values());
+        Health {
+            name: self.name.clone(),
+            address: self.address.clone(),
+            level,
+            message,
+            values,
+        }
+    }
+}
+
+// live status of a recording, refreshed every second also when it is not
+// shown. everything else the recorder prints goes through `log` so that it
+// does not end up inside the status block.
+pub struct Dashboard {
+    mode: Mode,
+    state: String,
+    connected: Instant,
+    last_refresh: Instant,
+    last_log: Instant,
+    lines: usize,
+    bytes: u64,
+    sensors: Vec<SensorStatus>,
+    split_devices: bool,
+}
+
+impl Dashboard {
+    pub fn new(sensors: &[Sensor], split_devices: bool, quiet: bool) -> Dashboard {
+        let mode = if quiet {
+            Mode::Quiet
+        } else if io::stdout().is_terminal() {
+            Mode::Live
+        } else {
+            Mode::Log
+        };
+        Dashboard {
+            mode,
+            state: "connected".to_string(),
+            connected: Instant::now(),
+            last_refresh: Instant::now(),
+            last_log: Instant::now(),
+            lines: 0,
+            bytes: 0,
+            sensors: sensors.iter().map(|sensor| SensorStatus::new(&sensor.name, &sensor.address)).collect(),
+            split_devices,
+        }
+    }
+
+    pub fn set_state(&mut self, state: &str) {
+        self.state = state.to_string();
+    }
+
+    pub fn set_closed(&mut self, sensor: usize, reason: &str) {
+        let sensor = &mut self.sensors[sensor];
+        sensor.closed = Some(reason.to_string());
+        for device in sensor.devices.values_mut() {
+            device.closed = Some(reason.to_string());
+        }
+    }
+
+    pub fn add(&mut self, sensor: usize, record: &Record) {
+        let sensor = &mut self.sensors[sensor];
+        sensor.add(record);
+        if !self.split_devices {
+            sensor.device_id = Some(record.header.device_id);
+            return;
+        }
+        // the sensor shows the whole stream, every device its part of it
+        let address = sensor.address.clone();
+        let device = sensor.devices.entry(record.header.device_id).or_insert_with(|| {
+            let mut device = SensorStatus::new(record.source.as_deref().unwrap_or(""), &address);
+            device.device_id = Some(record.header.device_id);
+            device
+        });
+        device.add(record);
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.last_refresh.elapsed().as_secs_f64() >= REFRESH_SECONDS
+    }
+
+    pub fn health(&self) -> Vec<Health> {
+        self.sensors
+            .iter()
+            .flat_map(|sensor| Some(sensor).into_iter().chain(sensor.devices.values()))
+            .map(|sensor| sensor.health(&self.state))
+            .collect()
+    }
+
+    // the recording first, then one group of lines per sensor and device
+    fn status(&self) -> Vec<Vec<(String, String)>> {
+        let elapsed = self.connected.elapsed().as_secs();
+        let recording = format!(
+            "{}, for {:02}:{:02}:{:02}, written {:.1} MB",
+            self.state,
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60,
+            self.bytes as f64 / 1024.0 / 1024.0
+        );
+        let mut groups = vec![vec![("recording".to_string(), recording)]];
+        for sensor in &self.sensors {
+            let (label, indent) = if self.sensors.len() == 1 { ("sensor", "") } else { (sensor.name.as_str(), "  ") };
+            groups.push(sensor.group(label, indent));
+            for device in sensor.devices.values() {
+                groups.push(device.group(&device.name, "  "));
+            }
+        }
+        groups
+    }
+
+    fn erase(&mut self) {
+        if self.lines > 0 {
+            print!("\x1b[{}A\x1b[J", self.lines);
+            self.lines = 0;
+        }
+    }
+
+    pub fn refresh(&mut self, bytes: u64) {
+        let elapsed = self.last_refresh.elapsed().as_secs_f64();
+        for sensor in &mut self.sensors {
+            sensor.refresh(elapsed);
+        }
+        self.bytes = bytes;
+        self.last_refresh = Instant::now();
+        if self.mode == Mode::Live || (self.mode == Mode::Log && self.last_log.elapsed().as_secs_f64() >= LOG_SECONDS) {
+            self.last_log = Instant::now();
+            self.render();
+        }
+    }
+
+    // the last status stays on screen, with the rates of the last refresh
+    pub fn finish(&mut self, state: &str, bytes: u64) {
+        self.state = state.to_string();
+        self.bytes = bytes;
+        self.render();
+        self.lines = 0;
+    }
+
+    fn render(&mut self) {
+        match self.mode {
+            Mode::Live => {
+                let lines: Vec<(String, String)> = self.status().into_iter().flatten().collect();
+                self.erase();
+                for (name, value) in &lines {
+                    println!("{:<14}{}\x1b[K", name, value);
+                }
+                self.lines = lines.len();
+            },
+            Mode::Log => {
+                // a single line for a single sensor, otherwise one per sensor
+                let mut groups = self.status();
+                if groups.len() == 2 {
+                    let sensor = groups.pop().unwrap();
+                    groups[0].extend(sensor);
+                }
+                let time = Local::now().format("%H:%M:%S");
+                for group in groups {
+                    let line: Vec<String> = group.iter().map(|(name, value)| format!("{}: {}", name.trim(), value)).collect();
+                    println!("{} {}", time, line.join(" | "));
+                }
+            },
+            Mode::Quiet => {},
+        }
+        let _ = io::stdout().flush();
+    }
+
+    pub fn log(&mut self, message: &str) {
+        if self.mode == Mode::Live {
+            self.erase();
+        }
+        println!("{}", message);
+    }
+}
@@ -0,0 +1,91 @@
+// frames, records and scans shared by the tests
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use stream::Record;
+use {Header, NtpTime, Point, Scan};
+
+pub const NTP_SECS: u32 = 3800000000;
+
+// a frame header of device 1 at `NTP_SECS`
+pub fn header(data_type: u16, size: usize) -> [u8; 24] {
+    let mut header = [0; 24];
+    BigEndian::write_u32(&mut header[0..4], 0xaffec0c2);
+    BigEndian::write_u32(&mut header[8..12], size as u32);
+    header[13] = 1;
+    BigEndian::write_u16(&mut header[14..16], data_type);
+    BigEndian::write_u32(&mut header[16..20], NTP_SECS);
+    header
+}
+
+pub fn record(data_type: u16, payload: &[u8]) -> Record {
+    record_at(data_type, payload, 0.0)
+}
+
+// a record `seconds` after `NTP_SECS`
+pub fn record_at(data_type: u16, payload: &[u8], seconds: f64) -> Record {
+    let mut header = header(data_type, payload.len());
+    let time = ntp_time(seconds);
+    BigEndian::write_u32(&mut header[16..20], time.secs);
+    BigEndian::write_u32(&mut header[20..24], time.precise);
+    Record::new(Header::new(header).unwrap(), payload)
+}
+
+pub fn ntp_time(seconds: f64) -> NtpTime {
+    NtpTime {
+        secs: NTP_SECS + seconds.trunc() as u32,
+        precise: (seconds.fract() * 4294967296.0) as u32,
+    }
+}
+
+// scan data without points that starts `seconds` after `NTP_SECS`
+pub fn scan_data(seconds: f64) -> Vec<u8> {
+    let mut payload = vec![0; 44];
+    let time = ntp_time(seconds);
+    LittleEndian::write_u32(&mut payload[6..10], time.precise);
+    LittleEndian::write_u32(&mut payload[10..14], time.secs);
+    payload
+}
+
+// object data with an object for every id, without contour points
+pub fn object_data(ids: &[u16]) -> Vec<u8> {
+    let mut payload = vec![0; 10 + 58 * ids.len()];
+    LittleEndian::write_u16(&mut payload[8..10], ids.len() as u16);
+    for (index, id) in ids.iter().enumerate() {
+        LittleEndian::write_u16(&mut payload[10 + 58 * index..], *id);
+    }
+    payload
+}
+
+pub fn point(x: f32, y: f32, z: f32) -> Point {
+    Point {
+        x,
+        y,
+        z,
+        angle: y.atan2(x),
+        distance: (x * x + y * y + z * z).sqrt(),
+        layer: 0,
+        echo: 0,
+        flags: 0,
+        echo_pulse_width: 0,
+        time: 0.0,
+        device_id: None,
+    }
+}
+
+pub fn scan(points: Vec<Point>) -> Scan {
+    Scan {
+        scan_number: 1,
+        start_time: ntp_time(0.0),
+        end_time: ntp_time(0.08),
+        points,
+    }
+}
+
+// unique to the test process, tests run in parallel
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("lidar-{}-{}", process::id(), name))
+}
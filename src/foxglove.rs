@@ -0,0 +1,462 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde_json::{self, Value};
+
+use dashboard::Health;
+use stream::{Message, Record};
+use {DataType, MovementData, NtpTime, ObjectData, Scan};
+
+// foxglove numeric types
+const UINT8: u8 = 1;
+const UINT16: u8 = 3;
+const FLOAT32: u8 = 7;
+
+const POINT_STRIDE: usize = 16;
+
+pub const POINT_CLOUD_SCHEMA: &str = r##"{
+  "title": "foxglove.PointCloud",
+  "type": "object",
+  "properties": {
+    "timestamp": {"$ref": "#/$defs/time"},
+    "frame_id": {"type": "string"},
+    "pose": {"$ref": "#/$defs/pose"},
+    "point_stride": {"type": "integer", "minimum": 0},
+    "fields": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "name": {"type": "string"},
+          "offset": {"type": "integer", "minimum": 0},
+          "type": {"type": "integer"}
+        }
+      }
+    },
+    "data": {"type": "string", "contentEncoding": "base64"}
+  },
+  "$defs": {
+    "time": {"type": "object", "properties": {"sec": {"type": "integer"}, "nsec": {"type": "integer"}}},
+    "vector3": {"type": "object", "properties": {"x": {"type": "number"}, "y": {"type": "number"}, "z": {"type": "number"}}},
+    "quaternion": {"type": "object", "properties": {"x": {"type": "number"}, "y": {"type": "number"}, "z": {"type": "number"}, "w": {"type": "number"}}},
+    "pose": {"type": "object", "properties": {"position": {"$ref": "#/$defs/vector3"}, "orientation": {"$ref": "#/$defs/quaternion"}}}
+  }
+}"##;
+
+pub const SCENE_UPDATE_SCHEMA: &str = r##"{
+  "title": "foxglove.SceneUpdate",
+  "type": "object",
+  "properties": {
+    "deletions": {"type": "array", "items": {"type": "object"}},
+    "entities": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "timestamp": {"$ref": "#/$defs/time"},
+          "frame_id": {"type": "string"},
+          "id": {"type": "string"},
+          "lifetime": {"$ref": "#/$defs/time"},
+          "frame_locked": {"type": "boolean"},
+          "cubes": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "properties": {
+                "pose": {"$ref": "#/$defs/pose"},
+                "size": {"$ref": "#/$defs/vector3"},
+                "color": {"$ref": "#/$defs/color"}
+              }
+            }
+          },
+          "arrows": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "properties": {
+                "pose": {"$ref": "#/$defs/pose"},
+                "shaft_length": {"type": "number"},
+                "shaft_diameter": {"type": "number"},
+                "head_length": {"type": "number"},
+                "head_diameter": {"type": "number"},
+                "color": {"$ref": "#/$defs/color"}
+              }
+            }
+          },
+          "texts": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "properties": {
+                "pose": {"$ref": "#/$defs/pose"},
+                "billboard": {"type": "boolean"},
+                "font_size": {"type": "number"},
+                "scale_invariant": {"type": "boolean"},
+                "color": {"$ref": "#/$defs/color"},
+                "text": {"type": "string"}
+              }
+            }
+          }
+        }
+      }
+    }
+  },
+  "$defs": {
+    "time": {"type": "object", "properties": {"sec": {"type": "integer"}, "nsec": {"type": "integer"}}},
+    "vector3": {"type": "object", "properties": {"x": {"type": "number"}, "y": {"type": "number"}, "z": {"type": "number"}}},
+    "quaternion": {"type": "object", "properties": {"x": {"type": "number"}, "y": {"type": "number"}, "z": {"type": "number"}, "w": {"type": "number"}}},
+    "pose": {"type": "object", "properties": {"position": {"$ref": "#/$defs/vector3"}, "orientation": {"$ref": "#/$defs/quaternion"}}},
+    "color": {"type": "object", "properties": {"r": {"type": "number"}, "g": {"type": "number"}, "b": {"type": "number"}, "a": {"type": "number"}}}
+  }
+}"##;
+
+pub const POSE_IN_FRAME_SCHEMA: &str = r##"{
+  "title": "foxglove.PoseInFrame",
+  "type": "object",
+  "properties": {
+    "timestamp": {"$ref": "#/$defs/time"},
+    "frame_id": {"type": "string"},
+    "pose": {"$ref": "#/$defs/pose"}
+  },
+  "$defs": {
+    "time": {"type": "object", "properties": {"sec": {"type": "integer"}, "nsec": {"type": "integer"}}},
+    "vector3": {"type": "object", "properties": {"x": {"type": "number"}, "y": {"type": "number"}, "z": {"type": "number"}}},
+    "quaternion": {"type": "object", "properties": {"x": {"type": "number"}, "y": {"type": "number"}, "z": {"type": "number"}, "w": {"type": "number"}}},
+    "pose": {"type": "object", "properties": {"position": {"$ref": "#/$defs/vector3"}, "orientation": {"$ref": "#/$defs/quaternion"}}}
+  }
+}"##;
+
+pub const ERROR_SCHEMA: &str = r##"{
+  "title": "lux.Error",
+  "type": "object",
+  "properties": {
+    "error_register_1": {"type": "integer"},
+    "error_register_2": {"type": "integer"},
+    "warning_register_1": {"type": "integer"},
+    "warning_register_2": {"type": "integer"}
+  }
+}"##;
+
+pub const SENSOR_INFO_SCHEMA: &str = r##"{
+  "title": "lux.SensorInfo",
+  "type": "object",
+  "properties": {
+    "firmware_version": {"type": "integer"},
+    "fpga_version": {"type": "integer"},
+    "scanner_status": {"type": "integer"},
+    "temperature": {"type": "integer"},
+    "serial_number_0": {"type": "integer"},
+    "serial_number_1": {"type": "integer"},
+    "fpga_version_date": {"type": "array", "items": {"type": "integer"}},
+    "dsp_version_date": {"type": "array", "items": {"type": "integer"}}
+  }
+}"##;
+
+pub const PAYLOAD_SCHEMA: &str = r##"{
+  "title": "lux.Payload",
+  "type": "object",
+  "properties": {
+    "data_type": {"type": "string"},
+    "data": {"type": "string", "contentEncoding": "base64"}
+  }
+}"##;
+
+pub const DIAGNOSTIC_ARRAY_SCHEMA: &str = r##"{
+  "title": "diagnostic_msgs/DiagnosticArray",
+  "type": "object",
+  "properties": {
+    "header": {
+      "type": "object",
+      "properties": {
+        "stamp": {"type": "object", "properties": {"sec": {"type": "integer"}, "nsec": {"type": "integer"}}},
+        "frame_id": {"type": "string"}
+      }
+    },
+    "status": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "level": {"type": "integer"},
+          "name": {"type": "string"},
+          "message": {"type": "string"},
+          "hardware_id": {"type": "string"},
+          "values": {
+            "type": "array",
+            "items": {"type": "object", "properties": {"key": {"type": "string"}, "value": {"type": "string"}}}
+          }
+        }
+      }
+    }
+  }
+}"##;
+
+pub struct Channel {
+    pub topic: String,
+    pub schema_name: &'static str,
+    pub schema: &'static str,
+}
+
+pub fn channel(data_type: &DataType) -> Channel {
+    let (topic, schema_name, schema) = match *data_type {
+        DataType::ScanData => ("scan", "foxglove.PointCloud", POINT_CLOUD_SCHEMA),
+        DataType::FusedScanData => ("fused_scan", "foxglove.PointCloud", POINT_CLOUD_SCHEMA),
+        DataType::ScalaScanData => ("scala_scan", "foxglove.PointCloud", POINT_CLOUD_SCHEMA),
+        DataType::FloatScanData => ("float_scan", "foxglove.PointCloud", POINT_CLOUD_SCHEMA),
+        DataType::ObjectData => ("objects", "foxglove.SceneUpdate", SCENE_UPDATE_SCHEMA),
+        DataType::FusedObjectData => ("fused_objects", "foxglove.SceneUpdate", SCENE_UPDATE_SCHEMA),
+        DataType::MovementData => ("movement", "foxglove.PoseInFrame", POSE_IN_FRAME_SCHEMA),
+        DataType::Error => ("error", "lux.Error", ERROR_SCHEMA),
+        DataType::SensorInfo => ("sensor_info", "lux.SensorInfo", SENSOR_INFO_SCHEMA),
+        DataType::Command => ("command", "lux.Payload", PAYLOAD_SCHEMA),
+        DataType::CommandReply => ("command_reply", "lux.Payload", PAYLOAD_SCHEMA),
+        DataType::EgoMotionData => ("ego_motion", "lux.Payload", PAYLOAD_SCHEMA),
+    };
+    Channel {
+        topic: format!("/lux/{}", topic),
+        schema_name,
+        schema,
+    }
+}
+
+// topics of a sensor are "/lux/<source>/..." when recording from more than
+// one, or from the devices behind an ecu
+pub fn record_channel(record: &Record) -> Channel {
+    let mut channel = channel(&record.data_type);
+    if let Some(ref source) = record.source {
+        channel.topic = channel.topic.replacen("/lux/", &format!("/lux/{}/", source), 1);
+    }
+    channel
+}
+
+pub fn diagnostics_channel() -> Channel {
+    Channel {
+        topic: "/diagnostics".to_string(),
+        schema_name: "diagnostic_msgs/DiagnosticArray",
+        schema: DIAGNOSTIC_ARRAY_SCHEMA,
+    }
+}
+
+// json message in the schema of `channel(&record.data_type)`
+pub fn encode(record: &Record) -> Vec<u8> {
+    let source = record.source.as_deref();
+    let value = match record.message {
+        Message::ScanData(_) | Message::FusedScanData(_) | Message::ScalaScanData(_) => {
+            // a scala scan without sub scans is an empty cloud at the message time
+            let scan = record.message.scan().unwrap_or_else(|| Scan {
+                scan_number: 0,
+                start_time: record.header.ntp_time.clone(),
+                end_time: record.header.ntp_time.clone(),
+                points: Vec::new(),
+            });
+            point_cloud(&scan, source.unwrap_or("lux"))
+        },
+        Message::ObjectData(ref object_data) => scene_update(object_data, source),
+        Message::FusedObjectData(ref object_data) => scene_update(&object_data.object_data(), source),
+        Message::MovementData(ref movement_data) => pose_in_frame(movement_data),
+        Message::Error(ref error_data) => serde_json::to_value(error_data).unwrap(),
+        Message::SensorInfo(ref sensor_info) => serde_json::to_value(sensor_info).unwrap(),
+        Message::Payload(ref payload) => json!({
+            "data_type": format!("{:?}", record.data_type),
+            "data": BASE64.encode(payload),
+        }),
+    };
+    serde_json::to_vec(&value).unwrap()
+}
+
+// a status per sensor in the schema of `diagnostics_channel()`
+pub fn diagnostics(health: &[Health], nanos: u64) -> Vec<u8> {
+    let status: Vec<Value> = health
+        .iter()
+        .map(|health| {
+            let values: Vec<Value> =
+                health.values.iter().map(|&(key, ref value)| json!({"key": key, "value": value})).collect();
+            json!({
+                "level": health.level,
+                "name": health.name,
+                "message": health.message,
+                "hardware_id": health.address,
+                "values": values,
+            })
+        })
+        .collect();
+    let value = json!({
+        "header": {"stamp": nanos_time(nanos), "frame_id": "lux"},
+        "status": status,
+    });
+    serde_json::to_vec(&value).unwrap()
+}
+
+fn nanos_time(nanos: u64) -> Value {
+    json!({"sec": nanos / 1000000000, "nsec": nanos % 1000000000})
+}
+
+fn time(ntp_time: &NtpTime) -> Value {
+    nanos_time(ntp_time.unix_nanos())
+}
+
+fn pose(x: f64, y: f64, yaw: f64) -> Value {
+    json!({
+        "position": {"x": x, "y": y, "z": 0.0},
+        "orientation": {"x": 0.0, "y": 0.0, "z": (yaw / 2.0).sin(), "w": (yaw / 2.0).cos()},
+    })
+}
+
+// the vehicle pose integrated by the ecu since it started
+fn pose_in_frame(movement_data: &MovementData) -> Value {
+    let (x, y, _) = movement_data.transform((0.0, 0.0, 0.0));
+    json!({
+        "timestamp": time(&movement_data.timestamp),
+        "frame_id": "odom",
+        "pose": pose(x, y, movement_data.course()),
+    })
+}
+
+fn point_cloud(scan: &Scan, frame_id: &str) -> Value {
+    let mut data: Vec<u8> = Vec::with_capacity(scan.points.len() * POINT_STRIDE);
+    for point in &scan.points {
+        data.write_f32::<LittleEndian>(point.x).unwrap();
+        data.write_f32::<LittleEndian>(point.y).unwrap();
+        data.write_f32::<LittleEndian>(point.z).unwrap();
+        data.write_u16::<LittleEndian>(point.echo_pulse_width).unwrap();
+        data.write_u8(point.layer).unwrap();
+        data.write_u8(point.echo).unwrap();
+    }
+    json!({
+        "timestamp": time(&scan.start_time),
+        "frame_id": frame_id,
+        "pose": pose(0.0, 0.0, 0.0),
+        "point_stride": POINT_STRIDE,
+        "fields": [
+            {"name": "x", "offset": 0, "type": FLOAT32},
+            {"name": "y", "offset": 4, "type": FLOAT32},
+            {"name": "z", "offset": 8, "type": FLOAT32},
+            {"name": "intensity", "offset": 12, "type": UINT16},
+            {"name": "layer", "offset": 14, "type": UINT8},
+            {"name": "echo", "offset": 15, "type": UINT8},
+        ],
+        "data": BASE64.encode(&data),
+    })
+}
+
+// entities of several sensors are told apart by their source
+fn scene_update(object_data: &ObjectData, source: Option<&str>) -> Value {
+    let color = json!({"r": 1.0, "g": 0.6, "b": 0.0, "a": 0.5});
+    let entities: Vec<Value> = object_data
+        .objects_vec
+        .iter()
+        .map(|object_info| {
+            // object positions and sizes are in cm
+            let x = (object_info.object_box_center.position_x as f64) / 100.0;
+            let y = (object_info.object_box_center.position_y as f64) / 100.0;
+            let velocity_x = (object_info.absolute_velocity.position_x as f64) / 100.0;
+            let velocity_y = (object_info.absolute_velocity.position_y as f64) / 100.0;
+            let speed = velocity_x.hypot(velocity_y);
+            json!({
+                "timestamp": time(&object_data.scan_start_timestamp),
+                "frame_id": source.unwrap_or("lux"),
+                "id": match source {
+                    Some(source) => format!("{}/{}", source, object_info.object_id),
+                    None => object_info.object_id.to_string(),
+                },
+                "lifetime": {"sec": 0, "nsec": 200000000},
+                "frame_locked": false,
+                "cubes": [{
+                    "pose": pose(x, y, object_info.orientation()),
+                    "size": {
+                        "x": (object_info.object_box_size.size_x as f64) / 100.0,
+                        "y": (object_info.object_box_size.size_y as f64) / 100.0,
+                        "z": 1.5,
+                    },
+                    "color": color,
+                }],
+                "arrows": if speed > 0.0 {
+                    vec![json!({
+                        "pose": pose(x, y, velocity_y.atan2(velocity_x)),
+                        "shaft_length": speed,
+                        "shaft_diameter": 0.1,
+                        "head_length": 0.3,
+                        "head_diameter": 0.3,
+                        "color": color,
+                    })]
+                } else {
+                    vec![]
+                },
+                "texts": [{
+                    "pose": pose(x, y, 0.0),
+                    "billboard": true,
+                    "font_size": 12.0,
+                    "scale_invariant": true,
+                    "color": {"r": 1.0, "g": 1.0, "b": 1.0, "a": 1.0},
+                    "text": object_info.object_id.to_string(),
+                }],
+            })
+        })
+        .collect();
+    json!({"deletions": [], "entities": entities})
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use byteorder::{ByteOrder, LittleEndian};
+    use num::FromPrimitive;
+    use serde_json::{self, Value};
+
+    use super::{channel, encode, record_channel};
+    use fixtures::{object_data, record, scan_data};
+    use DataType;
+
+    const DATA_TYPES: &[u16] = &[
+        0x2010, 0x2020, 0x2030, 0x2202, 0x2205, 0x2208, 0x2209, 0x2221, 0x2225, 0x2805, 0x2850, 0x7100,
+    ];
+
+    #[test]
+    fn channels() {
+        let mut topics = HashSet::new();
+        for &code in DATA_TYPES {
+            let channel = channel(&DataType::from_u16(code).unwrap());
+            assert!(topics.insert(channel.topic.clone()), "{}", channel.topic);
+            assert!(channel.topic.starts_with("/lux/"));
+            let schema: Value = serde_json::from_str(channel.schema).unwrap();
+            assert_eq!(schema["title"], channel.schema_name);
+        }
+    }
+
+    #[test]
+    fn sources_get_their_own_topics() {
+        let mut record = record(0x2030, &[0; 8]);
+        record.source = Some("front".to_string());
+        assert_eq!(record_channel(&record).topic, "/lux/front/error");
+    }
+
+    // every field of a message is in the schema of its channel
+    #[test]
+    fn messages_match_their_schemas() {
+        let mut movement = [0; 38];
+        LittleEndian::write_i32(&mut movement[20..24], 12500);
+        LittleEndian::write_i16(&mut movement[28..30], 15708);
+        let records = vec![
+            record(0x2202, &scan_data(0.0)),
+            record(0x2221, &object_data(&[1])),
+            record(0x2805, &movement),
+            record(0x2030, &[0; 8]),
+            record(0x7100, &[0; 30]),
+            record(0x2010, &[0x10, 0, 1, 2]),
+        ];
+        for record in &records {
+            let channel = record_channel(record);
+            let schema: Value = serde_json::from_str(channel.schema).unwrap();
+            let message: Value = serde_json::from_slice(&encode(record)).unwrap();
+            for key in message.as_object().unwrap().keys() {
+                assert!(!schema["properties"][key].is_null(), "{} in {}", key, channel.schema_name);
+            }
+        }
+        let pose: Value = serde_json::from_slice(&encode(&records[2])).unwrap();
+        assert_eq!(pose["frame_id"], "odom");
+        assert_eq!(pose["pose"]["position"]["x"], 1.25);
+        let orientation = &pose["pose"]["orientation"];
+        assert!((orientation["z"].as_f64().unwrap() - 0.5f64.sqrt()).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,86 @@
+use std::io::{self, Read};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use Header;
+
+const MAGIC_WORD: u32 = 0xaffec0c2;
+
+// a raw frame as it was received from the sensor
+pub struct Frame {
+    pub offset: u64,
+    pub header: [u8; 24],
+    pub payload: Vec<u8>,
+}
+
+pub struct FrameReader<R> {
+    reader: R,
+    offset: u64,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(reader: R) -> FrameReader<R> {
+        FrameReader { reader, offset: 0 }
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut header: [u8; 24] = [0; 24];
+        if self.reader.read_exact(&mut header).is_err() {
+            return None;
+        }
+        if BigEndian::read_u32(&header[0..4]) != MAGIC_WORD {
+            return None;
+        }
+        let size_of_message_data = BigEndian::read_u32(&header[8..12]);
+        let mut payload: Vec<u8> = vec![0; size_of_message_data as usize];
+        if self.reader.read_exact(&mut payload).is_err() {
+            return None;
+        }
+        let offset = self.offset;
+        self.offset += 24 + payload.len() as u64;
+        Some(Frame { offset, header, payload })
+    }
+}
+
+pub(crate) struct IdcReader<R> {
+    frames: FrameReader<R>,
+}
+
+impl<R: Read> IdcReader<R> {
+    pub fn new(reader: R) -> IdcReader<R> {
+        IdcReader {
+            frames: FrameReader::new(reader),
+        }
+    }
+}
+
+impl<R: Read> Iterator for IdcReader<R> {
+    type Item = (Header, Vec<u8>);
+
+    fn next(&mut self) -> Option<(Header, Vec<u8>)> {
+        // skip messages of unsupported data types
+        self.frames.find_map(|frame| Header::new(frame.header).map(|header| (header, frame.payload)))
+    }
+}
+
+// truncates after the last complete frame
+pub fn repair(data: &mut Vec<u8>) -> io::Result<bool> {
+    let mut position = 0;
+    while position + 24 <= data.len() && BigEndian::read_u32(&data[position..position + 4]) == MAGIC_WORD {
+        let next = position + 24 + BigEndian::read_u32(&data[position + 8..position + 12]) as usize;
+        if next > data.len() {
+            break;
+        }
+        position = next;
+    }
+    if position == 0 && !data.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no complete frame"));
+    }
+    let repaired = position < data.len();
+    data.truncate(position);
+    Ok(repaired)
+}
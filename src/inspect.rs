@@ -0,0 +1,188 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder};
+use num::FromPrimitive;
+use serde_yaml;
+
+use compression;
+use idc::FrameReader;
+use output;
+use stream::{Format, Message, Record, StreamReader};
+use {DataType, Header, NtpTime};
+
+// a message of a recording. idc files keep the raw payload and the offset of
+// every frame, stream formats only the payload of types without a decoder.
+struct Entry {
+    offset: Option<u64>,
+    header: [u8; 24],
+    payload: Option<Vec<u8>>,
+    message: Option<Message>,
+}
+
+impl Entry {
+    fn from_record(record: Record) -> Entry {
+        let mut header = [0; 24];
+        record.header.encode(&mut header);
+        match record.message {
+            Message::Payload(payload) => Entry {
+                offset: None,
+                header,
+                payload: Some(payload),
+                message: None,
+            },
+            message => Entry {
+                offset: None,
+                header,
+                payload: None,
+                message: Some(message),
+            },
+        }
+    }
+
+    fn data_type(&self) -> u16 {
+        BigEndian::read_u16(&self.header[14..16])
+    }
+
+    fn size(&self) -> u32 {
+        BigEndian::read_u32(&self.header[8..12])
+    }
+
+    fn ntp_time(&self) -> NtpTime {
+        NtpTime {
+            secs: BigEndian::read_u32(&self.header[16..20]),
+            precise: BigEndian::read_u32(&self.header[20..24]),
+        }
+    }
+
+    // decodes the payload of an idc frame on demand
+    fn message(&mut self) -> Option<&Message> {
+        if self.message.is_none() && has_decoder(self.data_type()) {
+            if let (Some(header), Some(ref payload)) = (Header::new(self.header), &self.payload) {
+                self.message = Some(Record::new(header, payload).message);
+            }
+        }
+        self.message.as_ref()
+    }
+}
+
+fn has_decoder(data_type: u16) -> bool {
+    matches!(
+        DataType::from_u16(data_type),
+        Some(DataType::ScanData)
+            | Some(DataType::FusedScanData)
+            | Some(DataType::ScalaScanData)
+            | Some(DataType::FloatScanData)
+            | Some(DataType::ObjectData)
+            | Some(DataType::FusedObjectData)
+            | Some(DataType::MovementData)
+            | Some(DataType::Error)
+            | Some(DataType::SensorInfo)
+    )
+}
+
+fn type_name(data_type: u16) -> String {
+    match DataType::from_u16(data_type) {
+        Some(data_type) => format!("{:?}", data_type),
+        None => "unknown".to_string(),
+    }
+}
+
+fn entries(path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<Entry>>>> {
+    let reader = compression::open(path)?;
+    Ok(match output::format_from_path(path) {
+        Some(format) => match Format::from_name(format) {
+            Some(format) => Box::new(StreamReader::new(reader, format).map(|record| record.map(Entry::from_record))),
+            None => {
+                let message = format!("cannot inspect {} recordings, only idc, yaml and ndjson", format);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            },
+        },
+        None => Box::new(FrameReader::new(reader).map(|frame| {
+            Ok(Entry {
+                offset: Some(frame.offset),
+                header: frame.header,
+                payload: Some(frame.payload),
+                message: None,
+            })
+        })),
+    })
+}
+
+// 16 bytes per line with their offset and printable characters
+fn hex_dump(data: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&byte| if (0x20..0x7f).contains(&byte) { byte as char } else { '.' })
+            .collect();
+        dump.push_str(&format!("    {:08x}  {:<47}  |{}|\n", line * 16, hex.join(" "), text));
+    }
+    dump
+}
+
+// one line per frame, followed by a hex dump of payloads without a decoder
+// when `hex` is set
+pub fn list<W: Write>(out: &mut W, path: &Path, hex: bool) -> io::Result<()> {
+    writeln!(out, "{:>7}  {:>10}  {:<24}  {:>7}  {:>6}  time", "frame", "offset", "type", "size", "device")?;
+    for (index, entry) in entries(path)?.enumerate() {
+        let entry = entry?;
+        let offset = entry.offset.map_or("-".to_string(), |offset| offset.to_string());
+        let data_type = format!("{} ({:#06x})", type_name(entry.data_type()), entry.data_type());
+        writeln!(
+            out,
+            "{:>7}  {:>10}  {:<24}  {:>7}  {:>6}  {}",
+            index,
+            offset,
+            data_type,
+            entry.size(),
+            entry.header[13],
+            entry.ntp_time().datetime().to_rfc3339()
+        )?;
+        if hex && !has_decoder(entry.data_type()) {
+            if let Some(ref payload) = entry.payload {
+                write!(out, "{}", hex_dump(payload))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// the header and the decoded message of one frame, or a hex dump of its
+// payload when there is no decoder for it or `hex` is set
+pub fn print_frame<W: Write>(out: &mut W, path: &Path, index: usize, hex: bool) -> io::Result<()> {
+    let mut entry = entries(path)?
+        .nth(index)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("there is no frame {}", index)))??;
+    let ntp_time = entry.ntp_time();
+    writeln!(out, "frame: {}", index)?;
+    if let Some(offset) = entry.offset {
+        writeln!(out, "offset: {}", offset)?;
+    }
+    writeln!(out, "magic_word: {:#010x}", BigEndian::read_u32(&entry.header[0..4]))?;
+    writeln!(out, "size_of_previous_messages: {}", BigEndian::read_u32(&entry.header[4..8]))?;
+    writeln!(out, "size_of_message_data: {}", entry.size())?;
+    writeln!(out, "device_id: {}", entry.header[13])?;
+    writeln!(out, "data_type: {} ({:#06x})", type_name(entry.data_type()), entry.data_type())?;
+    writeln!(out, "ntp_time: {} {} ({})", ntp_time.secs, ntp_time.precise, ntp_time.datetime().to_rfc3339())?;
+    if !hex {
+        if let Some(message) = entry.message() {
+            let document = serde_yaml::to_string(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            writeln!(out, "message:")?;
+            for line in document.lines().skip_while(|line| *line == "---") {
+                writeln!(out, "  {}", line)?;
+            }
+            return Ok(());
+        }
+    }
+    match entry.payload {
+        Some(ref payload) => {
+            writeln!(out, "payload:")?;
+            write!(out, "{}", hex_dump(payload))?;
+        },
+        None => writeln!(out, "payload: only the decoded message was recorded")?,
+    }
+    Ok(())
+}
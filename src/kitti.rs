@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use {ObjectData, Scan};
+
+// lux objects have no height, so labels use a nominal one
+const OBJECT_HEIGHT: f64 = 1.5;
+// the echo pulse width that maps to a reflectance of 1, in cm
+const MAX_ECHO_PULSE_WIDTH: f32 = 500.0;
+
+pub struct KittiWriter {
+    dir: PathBuf,
+    labels: bool,
+    timestamps: BufWriter<File>,
+    index: usize,
+    // scan start time -> frame index, to match object data with its scan
+    frames: HashMap<(u32, u32), usize>,
+}
+
+impl KittiWriter {
+    pub fn create(dir: &Path, labels: bool) -> io::Result<KittiWriter> {
+        fs::create_dir_all(dir.join("velodyne"))?;
+        if labels {
+            fs::create_dir_all(dir.join("label_2"))?;
+        }
+        Ok(KittiWriter {
+            dir: dir.to_path_buf(),
+            labels,
+            timestamps: BufWriter::new(File::create(dir.join("timestamps.txt"))?),
+            index: 0,
+            frames: HashMap::new(),
+        })
+    }
+
+    pub fn write_scan(&mut self, scan: &Scan) -> io::Result<()> {
+        let path = self.dir.join("velodyne").join(format!("{:06}.bin", self.index));
+        let mut file = BufWriter::new(File::create(path)?);
+        for point in &scan.points {
+            file.write_f32::<LittleEndian>(point.x)?;
+            file.write_f32::<LittleEndian>(point.y)?;
+            file.write_f32::<LittleEndian>(point.z)?;
+            // reflectance in [0, 1] from the echo pulse width
+            file.write_f32::<LittleEndian>(((point.echo_pulse_width as f32) / MAX_ECHO_PULSE_WIDTH).min(1.0))?;
+        }
+        file.flush()?;
+        // an empty label file unless object data for this scan follows
+        if self.labels {
+            File::create(self.label_path(self.index))?;
+        }
+
+        let datetime = scan.start_time.datetime();
+        writeln!(self.timestamps, "{}", datetime.format("%Y-%m-%d %H:%M:%S%.9f"))?;
+        self.timestamps.flush()?;
+
+        let time = &scan.start_time;
+        self.frames.insert((time.secs, time.precise), self.index);
+        self.index += 1;
+        Ok(())
+    }
+
+    fn label_path(&self, index: usize) -> PathBuf {
+        self.dir.join("label_2").join(format!("{:06}.txt", index))
+    }
+
+    pub fn write_objects(&mut self, object_data: &ObjectData) -> io::Result<()> {
+        if !self.labels {
+            return Ok(());
+        }
+        let time = &object_data.scan_start_timestamp;
+        let index = match self.frames.get(&(time.secs, time.precise)) {
+            Some(index) => *index,
+            None => return Ok(()),
+        };
+        let mut file = BufWriter::new(File::create(self.label_path(index))?);
+        for object_info in &object_data.objects_vec {
+            // lidar (x forward, y left, z up, cm) to kitti camera (x right, y down, z forward, m)
+            let x = (object_info.object_box_center.position_x as f64) / 100.0;
+            let y = (object_info.object_box_center.position_y as f64) / 100.0;
+            let length = (object_info.object_box_size.size_x as f64) / 100.0;
+            let width = (object_info.object_box_size.size_y as f64) / 100.0;
+            let yaw = object_info.orientation();
+            let rotation_y = -yaw - PI / 2.0;
+            let alpha = rotation_y - (-y).atan2(x);
+            writeln!(
+                file,
+                "Misc 0.00 0 {:.2} 0.00 0.00 0.00 0.00 {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} {:.2}",
+                alpha,
+                OBJECT_HEIGHT,
+                width,
+                length,
+                -y,
+                0.0,
+                x,
+                rotation_y
+            )?;
+        }
+        file.flush()
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use chrono::{Datelike, Utc};
+
+use {gps_time, MovementData, Scan};
+
+// LAS 1.4 with point data record format 6
+const HEADER_SIZE: u16 = 375;
+const POINT_DATA_RECORD_FORMAT: u8 = 6;
+const POINT_DATA_RECORD_LENGTH: u16 = 30;
+const SCALE: f64 = 0.001;
+
+pub struct LasWriter {
+    file: BufWriter<File>,
+    number_of_points: u64,
+    number_of_points_by_return: [u64; 15],
+    min: [f64; 3],
+    max: [f64; 3],
+}
+
+impl LasWriter {
+    pub fn create(path: &Path) -> io::Result<LasWriter> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&[0; HEADER_SIZE as usize])?;
+        Ok(LasWriter {
+            file,
+            number_of_points: 0,
+            number_of_points_by_return: [0; 15],
+            min: [f64::MAX; 3],
+            max: [f64::MIN; 3],
+        })
+    }
+
+    pub fn write_scan(&mut self, scan: &Scan, pose: Option<&MovementData>) -> io::Result<()> {
+        let mut number_of_returns: HashMap<(u8, u32), u8> = HashMap::new();
+        for point in &scan.points {
+            let count = number_of_returns.entry((point.layer, point.angle.to_bits())).or_insert(0);
+            *count = (*count).max(point.echo.saturating_add(1).min(15));
+        }
+
+        for point in &scan.points {
+            let (x, y, z) = match pose {
+                Some(movement_data) => movement_data.transform((point.x, point.y, point.z)),
+                None => (point.x as f64, point.y as f64, point.z as f64),
+            };
+            for (i, value) in [x, y, z].iter().enumerate() {
+                self.min[i] = self.min[i].min(*value);
+                self.max[i] = self.max[i].max(*value);
+            }
+
+            let return_number = point.echo.saturating_add(1).min(15);
+            let returns = number_of_returns[&(point.layer, point.angle.to_bits())];
+            let scan_angle = (point.angle as f64).to_degrees() / 0.006;
+
+            self.file.write_i32::<LittleEndian>((x / SCALE).round() as i32)?;
+            self.file.write_i32::<LittleEndian>((y / SCALE).round() as i32)?;
+            self.file.write_i32::<LittleEndian>((z / SCALE).round() as i32)?;
+            self.file.write_u16::<LittleEndian>(point.echo_pulse_width)?;
+            self.file.write_u8(return_number | (returns << 4))?;
+            // scanner channel holds the layer of a four layer scanner, the
+            // point source id below the full layer of eight layer scanners
+            self.file.write_u8((point.layer & 0x03) << 4)?;
+            self.file.write_u8(0)?;
+            // user data holds the low byte of the flags, all of them for the lux
+            self.file.write_u8(point.flags as u8)?;
+            self.file.write_i16::<LittleEndian>(scan_angle.round() as i16)?;
+            self.file.write_u16::<LittleEndian>(point.layer as u16)?;
+            // adjusted standard gps time
+            self.file.write_f64::<LittleEndian>(gps_time(point.time) - 1.0e9)?;
+
+            self.number_of_points += 1;
+            self.number_of_points_by_return[(return_number - 1) as usize] += 1;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.number_of_points == 0 {
+            self.min = [0.0; 3];
+            self.max = [0.0; 3];
+        }
+        self.file.flush()?;
+        self.file.seek(SeekFrom::Start(0))?;
+        let now = Utc::now();
+
+        let file = &mut self.file;
+        file.write_all(b"LASF")?;
+        file.write_u16::<LittleEndian>(0)?;
+        // adjusted standard gps time, wkt coordinate system
+        file.write_u16::<LittleEndian>(0x0011)?;
+        file.write_all(&[0; 16])?;
+        file.write_u8(1)?;
+        file.write_u8(4)?;
+        write_padded(file, "LUX", 32)?;
+        write_padded(file, "lidar", 32)?;
+        file.write_u16::<LittleEndian>(now.ordinal() as u16)?;
+        file.write_u16::<LittleEndian>(now.year() as u16)?;
+        file.write_u16::<LittleEndian>(HEADER_SIZE)?;
+        file.write_u32::<LittleEndian>(HEADER_SIZE as u32)?;
+        file.write_u32::<LittleEndian>(0)?;
+        file.write_u8(POINT_DATA_RECORD_FORMAT)?;
+        file.write_u16::<LittleEndian>(POINT_DATA_RECORD_LENGTH)?;
+        // legacy point counts are zero for point data record format 6
+        file.write_all(&[0; 24])?;
+        for _ in 0..3 {
+            file.write_f64::<LittleEndian>(SCALE)?;
+        }
+        for _ in 0..3 {
+            file.write_f64::<LittleEndian>(0.0)?;
+        }
+        for i in 0..3 {
+            file.write_f64::<LittleEndian>(self.max[i])?;
+            file.write_f64::<LittleEndian>(self.min[i])?;
+        }
+        file.write_u64::<LittleEndian>(0)?;
+        file.write_u64::<LittleEndian>(0)?;
+        file.write_u32::<LittleEndian>(0)?;
+        file.write_u64::<LittleEndian>(self.number_of_points)?;
+        for count in self.number_of_points_by_return.iter() {
+            file.write_u64::<LittleEndian>(*count)?;
+        }
+        file.flush()
+    }
+}
+
+fn write_padded<W: Write>(writer: &mut W, text: &str, len: usize) -> io::Result<()> {
+    let mut buffer = vec![0; len];
+    let bytes = text.as_bytes();
+    let n = bytes.len().min(len);
+    buffer[..n].copy_from_slice(&bytes[..n]);
+    writer.write_all(&buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::{LasWriter, HEADER_SIZE, POINT_DATA_RECORD_LENGTH};
+    use fixtures::{point, scan, temp_path};
+    use Point;
+
+    #[test]
+    fn echo_and_layer() {
+        let point = |layer, echo| Point {
+            layer,
+            echo,
+            ..point(1.0, 0.0, 0.0)
+        };
+        let scan = scan(vec![point(6, 0), point(6, 255)]);
+        let path = temp_path("echo.las");
+        let mut writer = LasWriter::create(&path).unwrap();
+        writer.write_scan(&scan, None).unwrap();
+        writer.finish().unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let points = &data[HEADER_SIZE as usize..];
+        assert_eq!(points.len(), 2 * POINT_DATA_RECORD_LENGTH as usize);
+        let last = &points[POINT_DATA_RECORD_LENGTH as usize..];
+        // the last of 15 returns
+        assert_eq!(last[14], 15 | (15 << 4));
+        assert_eq!(LittleEndian::read_u16(&last[20..22]), 6);
+        assert_eq!(LittleEndian::read_u64(&data[247..255]), 2);
+        assert_eq!(LittleEndian::read_u64(&data[255..263]), 1);
+        assert_eq!(LittleEndian::read_u64(&data[255 + 14 * 8..255 + 15 * 8]), 1);
+    }
+}
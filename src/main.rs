@@ -1,32 +1,103 @@
+extern crate base64;
 extern crate byteorder;
 extern crate chrono;
+extern crate crossterm;
+extern crate ctrlc;
+extern crate flate2;
 extern crate getopts;
 extern crate num;
+extern crate parquet;
+extern crate serde;
 #[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
+extern crate serde_path_to_error;
 extern crate serde_yaml;
-extern crate time;
+extern crate sha2;
+extern crate tungstenite;
+extern crate zstd;
+
+mod columnar;
+mod compression;
+mod config;
+mod csv;
+mod dashboard;
+#[cfg(test)]
+mod fixtures;
+mod foxglove;
+mod idc;
+mod inspect;
+mod kitti;
+mod las;
+mod manifest;
+mod mcap;
+mod output;
+mod pcd;
+mod ply;
+mod repair;
+mod replay;
+mod rosbag;
+mod rotate;
+mod select;
+mod sensor;
+mod stats;
+mod stream;
+mod view;
+mod websocket;
+mod window;
 
-use std::io::prelude::*;
 use std::cmp::PartialEq;
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::File;
-use std::io::{BufWriter, Write, BufReader, Read};
+use std::ffi::OsStr;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write, Read};
 use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use chrono::prelude::*;
-use getopts::Options;
-use num::{FromPrimitive, pow, ToPrimitive};
-use time::Duration;
+use getopts::{Matches, Options};
+use num::{FromPrimitive, ToPrimitive};
+use chrono::Duration;
 
+use columnar::ParquetWriter;
+use config::{Config, Sensor};
+use csv::ObjectCsvWriter;
+use dashboard::Dashboard;
+use idc::IdcReader;
+use kitti::KittiWriter;
+use las::LasWriter;
+use manifest::{ManifestWriter, MountingPose};
+use output::RecordWriter;
+use rotate::{Rotation, RotatingWriter};
+use select::SplitWriter;
+use sensor::{Connection, Event};
+use stats::Stats;
+use stream::{Format, Message, Record, StreamReader};
+use websocket::Server;
+use window::Window;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+const SENSOR_ADDRESS: &str = "192.168.0.1:12002";
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 enum DataType {
     Command = 0x2010,
     CommandReply = 0x2020,
     Error = 0x2030,
     ScanData = 0x2202,
+    FusedScanData = 0x2205,
+    ScalaScanData = 0x2208,
+    FloatScanData = 0x2209,
     ObjectData = 0x2221,
+    FusedObjectData = 0x2225,
     MovementData = 0x2805,
     EgoMotionData = 0x2850,
     SensorInfo = 0x7100,
@@ -39,7 +110,11 @@ impl FromPrimitive for DataType {
             0x2020 => Some(DataType::CommandReply),
             0x2030 => Some(DataType::Error),
             0x2202 => Some(DataType::ScanData),
+            0x2205 => Some(DataType::FusedScanData),
+            0x2208 => Some(DataType::ScalaScanData),
+            0x2209 => Some(DataType::FloatScanData),
             0x2221 => Some(DataType::ObjectData),
+            0x2225 => Some(DataType::FusedObjectData),
             0x2805 => Some(DataType::MovementData),
             0x2850 => Some(DataType::EgoMotionData),
             0x7100 => Some(DataType::SensorInfo),
@@ -53,7 +128,11 @@ impl FromPrimitive for DataType {
             0x2020 => Some(DataType::CommandReply),
             0x2030 => Some(DataType::Error),
             0x2202 => Some(DataType::ScanData),
+            0x2205 => Some(DataType::FusedScanData),
+            0x2208 => Some(DataType::ScalaScanData),
+            0x2209 => Some(DataType::FloatScanData),
             0x2221 => Some(DataType::ObjectData),
+            0x2225 => Some(DataType::FusedObjectData),
             0x2805 => Some(DataType::MovementData),
             0x2850 => Some(DataType::EgoMotionData),
             0x7100 => Some(DataType::SensorInfo),
@@ -65,33 +144,48 @@ impl FromPrimitive for DataType {
 impl ToPrimitive for DataType {
     fn to_i64(self: &DataType) -> Option<i64> {
         match self {
-                &DataType::Command => Some(0x2010),
-                &DataType::CommandReply => Some(0x2020),
-                &DataType::Error => Some(0x2030),
-                &DataType::ScanData => Some(0x2202),
-                &DataType::ObjectData => Some(0x2221),
-                &DataType::MovementData => Some(0x2805),
-                &DataType::EgoMotionData => Some(0x2850),
-                &DataType::SensorInfo => Some(0x7100),
-                _ => None,
+            DataType::Command => Some(0x2010),
+            DataType::CommandReply => Some(0x2020),
+            DataType::Error => Some(0x2030),
+            DataType::ScanData => Some(0x2202),
+            DataType::FusedScanData => Some(0x2205),
+            DataType::ScalaScanData => Some(0x2208),
+            DataType::FloatScanData => Some(0x2209),
+            DataType::ObjectData => Some(0x2221),
+            DataType::FusedObjectData => Some(0x2225),
+            DataType::MovementData => Some(0x2805),
+            DataType::EgoMotionData => Some(0x2850),
+            DataType::SensorInfo => Some(0x7100),
         }
     }
 
     fn to_u64(self: &DataType) -> Option<u64> {
         match self {
-                &DataType::Command => Some(0x2010),
-                &DataType::CommandReply => Some(0x2020),
-                &DataType::Error => Some(0x2030),
-                &DataType::ScanData => Some(0x2202),
-                &DataType::ObjectData => Some(0x2221),
-                &DataType::MovementData => Some(0x2805),
-                &DataType::EgoMotionData => Some(0x2850),
-                &DataType::SensorInfo => Some(0x7100),
-                _ => None,
+            DataType::Command => Some(0x2010),
+            DataType::CommandReply => Some(0x2020),
+            DataType::Error => Some(0x2030),
+            DataType::ScanData => Some(0x2202),
+            DataType::FusedScanData => Some(0x2205),
+            DataType::ScalaScanData => Some(0x2208),
+            DataType::FloatScanData => Some(0x2209),
+            DataType::ObjectData => Some(0x2221),
+            DataType::FusedObjectData => Some(0x2225),
+            DataType::MovementData => Some(0x2805),
+            DataType::EgoMotionData => Some(0x2850),
+            DataType::SensorInfo => Some(0x7100),
         }
     }
 }
 
+impl DataType {
+    fn is_scan(&self) -> bool {
+        matches!(
+            *self,
+            DataType::ScanData | DataType::FusedScanData | DataType::ScalaScanData | DataType::FloatScanData
+        )
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct Header {
     magic_word: u32,
@@ -104,40 +198,20 @@ struct Header {
 }
 
 impl Header {
-    fn new(buffer: [u8; 24]) -> Header {
-        Header {
+    // none for data types without a `DataType`
+    fn new(buffer: [u8; 24]) -> Option<Header> {
+        Some(Header {
             magic_word: BigEndian::read_u32(&buffer[0..4]),
             size_of_previous_messages: BigEndian::read_u32(&buffer[4..8]),
             size_of_message_data: BigEndian::read_u32(&buffer[8..12]),
             reserved: buffer[12],
             device_id: buffer[13],
-            data_type: DataType::from_u16(BigEndian::read_u16(&buffer[14..16])).unwrap(),
+            data_type: DataType::from_u16(BigEndian::read_u16(&buffer[14..16]))?,
             ntp_time: NtpTime {
                 secs: BigEndian::read_u32(&buffer[16..20]),
                 precise: BigEndian::read_u32(&buffer[20..24]),
             },
-        }
-    }
-
-    fn print(&self) {
-        println!("magic_word: {:x}", self.magic_word);
-        println!("size_of_previous_messages: {}", self.size_of_previous_messages);
-        println!("size_of_message_data: {}", self.size_of_message_data);
-        println!("reserved: {:x}", self.reserved);
-        println!("device_id: {}", self.device_id);
-        match self.data_type {
-            DataType::Command => println!("data_type: Command"),
-            DataType::CommandReply => println!("data_type: CommandReply"),
-            DataType::Error => println!("data_type: Error"),
-            DataType::ScanData => println!("data_type: ScanData"),
-            DataType::ObjectData => println!("data_type: ObjectData"),
-            DataType::MovementData => println!("data_type: MovementData"),
-            DataType::EgoMotionData => println!("data_type: EgoMotionData"),
-            DataType::SensorInfo => println!("data_type: SensorInfo"),
-            _ => {},
-        }
-        print!("ntp_time: ");
-        self.ntp_time.print_datetime();
+        })
     }
 
     fn encode(&self, buffer: &mut [u8]) {
@@ -174,7 +248,11 @@ struct ScanData {
 }
 
 impl ScanData {
-    fn new(buffer: &[u8]) -> ScanData {
+    // none when the payload is shorter than its point count says
+    fn new(buffer: &[u8]) -> Option<ScanData> {
+        if buffer.len() < 44 {
+            return None;
+        }
         let mut scan_data = ScanData {
             scan_number: LittleEndian::read_u16(&buffer[0..2]),
             scanner_status: LittleEndian::read_u16(&buffer[2..4]),
@@ -202,11 +280,50 @@ impl ScanData {
         };
         let mut offset: usize = 44;
         let data_len: usize = 10;
-        for i in 0..(scan_data.scan_points as usize) {
-            scan_data.scan_point_vec.push(ScanPoint::new(&buffer[offset..offset+data_len]));
-            offset = offset + data_len;
+        for _ in 0..(scan_data.scan_points as usize) {
+            scan_data.scan_point_vec.push(ScanPoint::new(buffer.get(offset..offset+data_len)?));
+            offset += data_len;
+        }
+        Some(scan_data)
+    }
+
+    fn scan(&self) -> Scan {
+        Scan {
+            scan_number: self.scan_number,
+            start_time: self.scan_start_time_ntp.clone(),
+            end_time: self.scan_end_time_ntp.clone(),
+            points: self
+                .scan_point_vec
+                .iter()
+                .map(|scan_point| {
+                    let (x, y, z) = scan_point.position(self.angle_ticks_per_rotation);
+                    Point {
+                        x,
+                        y,
+                        z,
+                        angle: 2.0 * PI * (scan_point.horizontal_angle as f32) / (self.angle_ticks_per_rotation as f32),
+                        distance: (scan_point.radical_distance as f32) / 100.0,
+                        layer: scan_point.layer(),
+                        echo: scan_point.echo(),
+                        flags: scan_point.flags as u16,
+                        echo_pulse_width: scan_point.echo_pulse_width,
+                        time: self.point_time(scan_point),
+                        device_id: None,
+                    }
+                })
+                .collect(),
         }
-        scan_data
+    }
+
+    fn point_time(&self, scan_point: &ScanPoint) -> f64 {
+        let start = self.scan_start_time_ntp.unix_timestamp();
+        let end = self.scan_end_time_ntp.unix_timestamp();
+        let angle_range = (self.start_angle as f64) - (self.end_angle as f64);
+        if angle_range == 0.0 {
+            return start;
+        }
+        let ratio = ((self.start_angle as f64) - (scan_point.horizontal_angle as f64)) / angle_range;
+        start + (end - start) * ratio.clamp(0.0, 1.0)
     }
 }
 
@@ -232,13 +349,407 @@ impl ScanPoint {
         }
     }
 
-    fn print(&self) {
-        println!("");
+    fn layer(&self) -> u8 {
+        self.layer_and_echo & 0x0f
+    }
+
+    fn echo(&self) -> u8 {
+        self.layer_and_echo >> 4
+    }
+
+    // x, y, z in meters. layers are 0.8 deg apart, centered on the scan plane.
+    fn position(&self, angle_ticks_per_rotation: u16) -> (f32, f32, f32) {
+        let distance = (self.radical_distance as f32) / 100.0;
+        let horizontal = 2.0 * PI * (self.horizontal_angle as f32) / (angle_ticks_per_rotation as f32);
+        let vertical = ((self.layer() as f32) - 1.5) * 0.8 * PI / 180.0;
+        (
+            distance * vertical.cos() * horizontal.cos(),
+            distance * vertical.cos() * horizontal.sin(),
+            distance * vertical.sin(),
+        )
     }
 }
 
-// ntp_time
+// a scan of any scan data type, the form every point cloud output takes.
+// positions in meters in the frame of the sensor, or of the vehicle for
+// fused scans.
+#[derive(Debug)]
+struct Scan {
+    scan_number: u16,
+    start_time: NtpTime,
+    end_time: NtpTime,
+    points: Vec<Point>,
+}
+
+impl Scan {
+    fn file_name(&self, extension: &str) -> String {
+        format!(
+            "{:05}_{}.{:010}.{}",
+            self.scan_number,
+            self.start_time.secs,
+            self.start_time.precise,
+            extension
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Point {
+    x: f32,
+    y: f32,
+    z: f32,
+    // horizontal angle in rad and radial distance in meters
+    angle: f32,
+    distance: f32,
+    layer: u8,
+    echo: u8,
+    flags: u16,
+    // in cm, like the lux
+    echo_pulse_width: u16,
+    // unix timestamp
+    time: f64,
+    // the scanner of a point in a fused scan
+    device_id: Option<u8>,
+}
+
+// scans of a fusion ecu (0x2205), big endian with the points in meters, as
+// in "Scan data 0x2205" of the ibeo data type specification: a 24 byte
+// header, 148 bytes per scanner info and 28 bytes per point. scans with float
+// coordinates (0x2209) are decoded with the same layout, which is an
+// assumption pinned by `tests::float_scan` and not taken from the
+// specification
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FusedScanData {
+    scan_start_time: NtpTime,
+    // in microseconds
+    end_time_offset: u32,
+    flags: u32,
+    scan_number: u16,
+    number_of_scan_points: u16,
+    number_of_scanner_infos: u8,
+    scanner_info_vec: Vec<ScannerInfo>,
+    scan_point_vec: Vec<FusedScanPoint>,
+}
+
+impl FusedScanData {
+    // none when the payload is shorter than its counts say
+    fn new(buffer: &[u8]) -> Option<FusedScanData> {
+        if buffer.len() < 24 {
+            return None;
+        }
+        let mut scan_data = FusedScanData {
+            scan_start_time: NtpTime {
+                secs: BigEndian::read_u32(&buffer[0..4]),
+                precise: BigEndian::read_u32(&buffer[4..8]),
+            },
+            end_time_offset: BigEndian::read_u32(&buffer[8..12]),
+            flags: BigEndian::read_u32(&buffer[12..16]),
+            scan_number: BigEndian::read_u16(&buffer[16..18]),
+            number_of_scan_points: BigEndian::read_u16(&buffer[18..20]),
+            number_of_scanner_infos: buffer[20],
+            scanner_info_vec: Vec::new(),
+            scan_point_vec: Vec::new(),
+        };
+        let mut offset: usize = 24;
+        for _ in 0..(scan_data.number_of_scanner_infos as usize) {
+            scan_data.scanner_info_vec.push(ScannerInfo::new(buffer.get(offset..offset+148)?));
+            offset += 148;
+        }
+        for _ in 0..(scan_data.number_of_scan_points as usize) {
+            scan_data.scan_point_vec.push(FusedScanPoint::new(buffer.get(offset..offset+28)?));
+            offset += 28;
+        }
+        Some(scan_data)
+    }
+
+    fn scan(&self) -> Scan {
+        let start = self.scan_start_time.unix_timestamp();
+        Scan {
+            scan_number: self.scan_number,
+            start_time: self.scan_start_time.clone(),
+            end_time: self.scan_start_time.add_micros(self.end_time_offset),
+            points: self
+                .scan_point_vec
+                .iter()
+                .map(|scan_point| Point {
+                    x: scan_point.x,
+                    y: scan_point.y,
+                    z: scan_point.z,
+                    angle: scan_point.y.atan2(scan_point.x),
+                    distance: (scan_point.x.powi(2) + scan_point.y.powi(2) + scan_point.z.powi(2)).sqrt(),
+                    layer: scan_point.layer,
+                    echo: scan_point.echo,
+                    flags: scan_point.flags,
+                    echo_pulse_width: (scan_point.echo_pulse_width * 100.0).round() as u16,
+                    time: start + (scan_point.time_offset as f64) / 1e6,
+                    device_id: Some(scan_point.device_id),
+                })
+                .collect(),
+        }
+    }
+}
+
+// one of the scanners whose scans were fused, angles in rad and the
+// mounting position in meters and rad
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ScannerInfo {
+    device_id: u8,
+    scanner_type: u8,
+    scan_number: u16,
+    scanner_status: u32,
+    start_angle: f32,
+    end_angle: f32,
+    scan_start_time: NtpTime,
+    scan_end_time: NtpTime,
+    scan_start_time_from_device: NtpTime,
+    scan_end_time_from_device: NtpTime,
+    scan_frequency: f32,
+    beam_tilt: f32,
+    scan_flags: u32,
+    mounting_position_yaw_angle: f32,
+    mounting_position_pitch_angle: f32,
+    mounting_position_roll_angle: f32,
+    mounting_position_x: f32,
+    mounting_position_y: f32,
+    mounting_position_z: f32,
+    // start angle and resolution of up to 8 sectors
+    resolution_vec: Vec<(f32, f32)>,
+}
+
+impl ScannerInfo {
+    fn new(buffer: &[u8]) -> ScannerInfo {
+        let float = |offset: usize| BigEndian::read_f32(&buffer[offset..offset+4]);
+        let time = |offset: usize| NtpTime {
+            secs: BigEndian::read_u32(&buffer[offset..offset+4]),
+            precise: BigEndian::read_u32(&buffer[offset+4..offset+8]),
+        };
+        ScannerInfo {
+            device_id: buffer[0],
+            scanner_type: buffer[1],
+            scan_number: BigEndian::read_u16(&buffer[2..4]),
+            scanner_status: BigEndian::read_u32(&buffer[4..8]),
+            start_angle: float(8),
+            end_angle: float(12),
+            scan_start_time: time(16),
+            scan_end_time: time(24),
+            scan_start_time_from_device: time(32),
+            scan_end_time_from_device: time(40),
+            scan_frequency: float(48),
+            beam_tilt: float(52),
+            scan_flags: BigEndian::read_u32(&buffer[56..60]),
+            mounting_position_yaw_angle: float(60),
+            mounting_position_pitch_angle: float(64),
+            mounting_position_roll_angle: float(68),
+            mounting_position_x: float(72),
+            mounting_position_y: float(76),
+            mounting_position_z: float(80),
+            resolution_vec: (0..8).map(|i| (float(84 + i * 8), float(88 + i * 8))).collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FusedScanPoint {
+    x: f32,
+    y: f32,
+    z: f32,
+    // in meters
+    echo_pulse_width: f32,
+    device_id: u8,
+    layer: u8,
+    echo: u8,
+    // since the scan start in microseconds
+    time_offset: u32,
+    flags: u16,
+    segment_id: u16,
+}
+
+impl FusedScanPoint {
+    fn new(buffer: &[u8]) -> FusedScanPoint {
+        FusedScanPoint {
+            x: BigEndian::read_f32(&buffer[0..4]),
+            y: BigEndian::read_f32(&buffer[4..8]),
+            z: BigEndian::read_f32(&buffer[8..12]),
+            echo_pulse_width: BigEndian::read_f32(&buffer[12..16]),
+            device_id: buffer[16],
+            layer: buffer[17],
+            echo: buffer[18],
+            time_offset: BigEndian::read_u32(&buffer[20..24]),
+            flags: BigEndian::read_u16(&buffer[24..26]),
+            segment_id: BigEndian::read_u16(&buffer[26..28]),
+        }
+    }
+}
+
+// scans of a scala, big endian, in sub scans of one mirror side each
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ScalaScanData {
+    scan_number: u16,
+    scanner_type: u16,
+    scanner_status: u16,
+    angle_ticks_per_rotation: u16,
+    processing_flags: u32,
+    mounting_position_yaw_angle: i16,
+    mounting_position_pitch_angle: i16,
+    mounting_position_roll_angle: i16,
+    mounting_position_x: i16,
+    mounting_position_y: i16,
+    mounting_position_z: i16,
+    device_id: u8,
+    number_of_sub_scans: u8,
+    sub_scan_vec: Vec<SubScan>,
+}
+
+impl ScalaScanData {
+    // none when the payload is shorter than its counts say
+    fn new(buffer: &[u8]) -> Option<ScalaScanData> {
+        if buffer.len() < 54 {
+            return None;
+        }
+        let mut scan_data = ScalaScanData {
+            scan_number: BigEndian::read_u16(&buffer[0..2]),
+            scanner_type: BigEndian::read_u16(&buffer[2..4]),
+            scanner_status: BigEndian::read_u16(&buffer[4..6]),
+            angle_ticks_per_rotation: BigEndian::read_u16(&buffer[6..8]),
+            processing_flags: BigEndian::read_u32(&buffer[8..12]),
+            mounting_position_yaw_angle: BigEndian::read_i16(&buffer[12..14]),
+            mounting_position_pitch_angle: BigEndian::read_i16(&buffer[14..16]),
+            mounting_position_roll_angle: BigEndian::read_i16(&buffer[16..18]),
+            mounting_position_x: BigEndian::read_i16(&buffer[18..20]),
+            mounting_position_y: BigEndian::read_i16(&buffer[20..22]),
+            mounting_position_z: BigEndian::read_i16(&buffer[22..24]),
+            device_id: buffer[50],
+            number_of_sub_scans: buffer[52],
+            sub_scan_vec: Vec::new(),
+        };
+        let mut offset: usize = 54;
+        for _ in 0..(scan_data.number_of_sub_scans as usize) {
+            let sub_scan = SubScan::new(buffer.get(offset..)?)?;
+            offset += 40 + (sub_scan.number_of_scan_points as usize) * 12;
+            scan_data.sub_scan_vec.push(sub_scan);
+        }
+        Some(scan_data)
+    }
+
+    // none without sub scans, which carry the times
+    fn scan(&self) -> Option<Scan> {
+        let first = self.sub_scan_vec.first()?;
+        let last = self.sub_scan_vec.last()?;
+        let ticks = self.angle_ticks_per_rotation as f32;
+        let mut points = Vec::new();
+        for sub_scan in &self.sub_scan_vec {
+            let tilt = (sub_scan.mirror_tilt as f32) / 500.0;
+            for scan_point in &sub_scan.scan_point_vec {
+                let distance = (scan_point.radial_distance as f32) / 100.0;
+                let horizontal = 2.0 * PI * (scan_point.horizontal_angle as f32) / ticks;
+                // layers are 0.8 deg apart, tilted with the mirror side
+                let vertical = (((scan_point.layer as f32) - 1.5) * 0.8 + tilt) * PI / 180.0;
+                points.push(Point {
+                    x: distance * vertical.cos() * horizontal.cos(),
+                    y: distance * vertical.cos() * horizontal.sin(),
+                    z: distance * vertical.sin(),
+                    angle: horizontal,
+                    distance,
+                    layer: scan_point.layer,
+                    echo: scan_point.echo,
+                    flags: scan_point.flags,
+                    echo_pulse_width: scan_point.echo_pulse_width,
+                    time: sub_scan.point_time(scan_point),
+                    device_id: None,
+                });
+            }
+        }
+        Some(Scan {
+            scan_number: self.scan_number,
+            start_time: first.start_time.clone(),
+            end_time: last.end_time.clone(),
+            points,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SubScan {
+    start_time: NtpTime,
+    end_time: NtpTime,
+    start_angle: i16,
+    end_angle: i16,
+    flags: u8,
+    mirror_side: u8,
+    // in 1/500 deg
+    mirror_tilt: i16,
+    number_of_scan_points: u16,
+    scan_point_vec: Vec<ScalaScanPoint>,
+}
+
+impl SubScan {
+    fn new(buffer: &[u8]) -> Option<SubScan> {
+        if buffer.len() < 40 {
+            return None;
+        }
+        let number_of_scan_points = BigEndian::read_u16(&buffer[38..40]);
+        if buffer.len() < 40 + (number_of_scan_points as usize) * 12 {
+            return None;
+        }
+        Some(SubScan {
+            start_time: NtpTime {
+                secs: BigEndian::read_u32(&buffer[0..4]),
+                precise: BigEndian::read_u32(&buffer[4..8]),
+            },
+            end_time: NtpTime {
+                secs: BigEndian::read_u32(&buffer[8..12]),
+                precise: BigEndian::read_u32(&buffer[12..16]),
+            },
+            start_angle: BigEndian::read_i16(&buffer[16..18]),
+            end_angle: BigEndian::read_i16(&buffer[18..20]),
+            flags: buffer[20],
+            mirror_side: buffer[21],
+            mirror_tilt: BigEndian::read_i16(&buffer[22..24]),
+            number_of_scan_points,
+            scan_point_vec: (0..number_of_scan_points as usize)
+                .map(|i| ScalaScanPoint::new(&buffer[40 + i * 12..52 + i * 12]))
+                .collect(),
+        })
+    }
+
+    fn point_time(&self, scan_point: &ScalaScanPoint) -> f64 {
+        let start = self.start_time.unix_timestamp();
+        let end = self.end_time.unix_timestamp();
+        let angle_range = (self.start_angle as f64) - (self.end_angle as f64);
+        if angle_range == 0.0 {
+            return start;
+        }
+        let ratio = ((self.start_angle as f64) - (scan_point.horizontal_angle as f64)) / angle_range;
+        start + (end - start) * ratio.clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ScalaScanPoint {
+    layer: u8,
+    echo: u8,
+    flags: u16,
+    horizontal_angle: i16,
+    // in cm
+    radial_distance: u16,
+    echo_pulse_width: u16,
+}
+
+impl ScalaScanPoint {
+    fn new(buffer: &[u8]) -> ScalaScanPoint {
+        ScalaScanPoint {
+            layer: buffer[0],
+            echo: buffer[1],
+            flags: BigEndian::read_u16(&buffer[2..4]),
+            horizontal_angle: BigEndian::read_i16(&buffer[4..6]),
+            radial_distance: BigEndian::read_u16(&buffer[6..8]),
+            echo_pulse_width: BigEndian::read_u16(&buffer[8..10]),
+        }
+    }
+}
+
+// ntp_time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct ObjectData {
     scan_start_timestamp: NtpTime,
     number_of_objects: u16,
@@ -246,7 +757,11 @@ struct ObjectData {
 }
 
 impl ObjectData {
-    fn new(buffer: &[u8]) -> ObjectData {
+    // none when the payload is shorter than its object counts say
+    fn new(buffer: &[u8]) -> Option<ObjectData> {
+        if buffer.len() < 10 {
+            return None;
+        }
         let mut object_data = ObjectData {
             scan_start_timestamp: NtpTime {
                 secs: LittleEndian::read_u32(&buffer[4..8]),
@@ -255,30 +770,138 @@ impl ObjectData {
             number_of_objects: LittleEndian::read_u16(&buffer[8..10]),
             objects_vec: Vec::new(),
         };
-        let mut number_of_contour_points: u16 = 0;
         let mut offset: usize = 10;
-        let mut data_len: usize = 0;
-        for i in 0..(object_data.number_of_objects as usize) {
-            number_of_contour_points = LittleEndian::read_u16(&buffer[offset+56..offset+58]);
-            data_len = 58 + (number_of_contour_points as usize) * 4;
-            object_data.objects_vec.push(ObjectInfo::new(&buffer[offset..offset+data_len]));
-            offset = offset + data_len;
+        for _ in 0..(object_data.number_of_objects as usize) {
+            let number_of_contour_points = LittleEndian::read_u16(buffer.get(offset+56..offset+58)?);
+            let data_len = 58 + (number_of_contour_points as usize) * 4;
+            object_data.objects_vec.push(ObjectInfo::new(buffer.get(offset..offset+data_len)?));
+            offset += data_len;
+        }
+        Some(object_data)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct MovementData {
+    timestamp: NtpTime,
+    scan_number: u16,
+    error_flags: u16,
+    longitudinal_velocity: i16,
+    steering_wheel_angle: i16,
+    wheel_angle: i16,
+    x_position: i32,
+    y_position: i32,
+    course_angle: i16,
+    time_diff: u16,
+    x_diff: i16,
+    y_diff: i16,
+    yaw_diff: i16,
+}
+
+impl MovementData {
+    fn new(buffer: &[u8]) -> Option<MovementData> {
+        if buffer.len() < 38 {
+            return None;
         }
-        object_data
+        Some(MovementData {
+            timestamp: NtpTime {
+                secs: LittleEndian::read_u32(&buffer[4..8]),
+                precise: LittleEndian::read_u32(&buffer[0..4]),
+            },
+            scan_number: LittleEndian::read_u16(&buffer[8..10]),
+            error_flags: LittleEndian::read_u16(&buffer[10..12]),
+            longitudinal_velocity: LittleEndian::read_i16(&buffer[12..14]),
+            steering_wheel_angle: LittleEndian::read_i16(&buffer[14..16]),
+            wheel_angle: LittleEndian::read_i16(&buffer[16..18]),
+            x_position: LittleEndian::read_i32(&buffer[20..24]),
+            y_position: LittleEndian::read_i32(&buffer[24..28]),
+            course_angle: LittleEndian::read_i16(&buffer[28..30]),
+            time_diff: LittleEndian::read_u16(&buffer[30..32]),
+            x_diff: LittleEndian::read_i16(&buffer[32..34]),
+            y_diff: LittleEndian::read_i16(&buffer[34..36]),
+            yaw_diff: LittleEndian::read_i16(&buffer[36..38]),
+        })
+    }
+
+    // course angle in 0.0001 rad
+    fn course(&self) -> f64 {
+        (self.course_angle as f64) / 10000.0
+    }
+
+    // vehicle pose: position in 0.0001 m
+    fn transform(&self, position: (f32, f32, f32)) -> (f64, f64, f64) {
+        let course = self.course();
+        let (x, y, z) = (position.0 as f64, position.1 as f64, position.2 as f64);
+        (
+            (self.x_position as f64) / 10000.0 + x * course.cos() - y * course.sin(),
+            (self.y_position as f64) / 10000.0 + x * course.sin() + y * course.cos(),
+            z,
+        )
     }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ErrorData {
+    error_register_1: u16,
+    error_register_2: u16,
+    warning_register_1: u16,
+    warning_register_2: u16,
+}
 
-    fn print(&self) {
-        print!("scan_start_timestamp: ");
-        self.scan_start_timestamp.print();
-        println!("number_of_object: {}", self.number_of_objects);
-        for i in 0..self.objects_vec.len() {
-            println!("object{}:", i);
-            self.objects_vec[i].print()
+impl ErrorData {
+    fn new(buffer: &[u8]) -> Option<ErrorData> {
+        if buffer.len() < 8 {
+            return None;
         }
+        Some(ErrorData {
+            error_register_1: LittleEndian::read_u16(&buffer[0..2]),
+            error_register_2: LittleEndian::read_u16(&buffer[2..4]),
+            warning_register_1: LittleEndian::read_u16(&buffer[4..6]),
+            warning_register_2: LittleEndian::read_u16(&buffer[6..8]),
+        })
     }
 }
 
+// same layout as the get status command reply
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SensorInfo {
+    firmware_version: u16,
+    fpga_version: u16,
+    scanner_status: u16,
+    temperature: u16,
+    serial_number_0: u16,
+    serial_number_1: u16,
+    fpga_version_date: [u16; 3],
+    dsp_version_date: [u16; 3],
+}
+
+impl SensorInfo {
+    fn new(buffer: &[u8]) -> Option<SensorInfo> {
+        if buffer.len() < 30 {
+            return None;
+        }
+        Some(SensorInfo {
+            firmware_version: LittleEndian::read_u16(&buffer[0..2]),
+            fpga_version: LittleEndian::read_u16(&buffer[2..4]),
+            scanner_status: LittleEndian::read_u16(&buffer[4..6]),
+            temperature: LittleEndian::read_u16(&buffer[10..12]),
+            serial_number_0: LittleEndian::read_u16(&buffer[12..14]),
+            serial_number_1: LittleEndian::read_u16(&buffer[14..16]),
+            fpga_version_date: [
+                LittleEndian::read_u16(&buffer[18..20]),
+                LittleEndian::read_u16(&buffer[20..22]),
+                LittleEndian::read_u16(&buffer[22..24]),
+            ],
+            dsp_version_date: [
+                LittleEndian::read_u16(&buffer[24..26]),
+                LittleEndian::read_u16(&buffer[26..28]),
+                LittleEndian::read_u16(&buffer[28..30]),
+            ],
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct ObjectInfo {
     object_id: u16,
     object_age: u16,
@@ -367,62 +990,175 @@ impl ObjectInfo {
         object_info
     }
 
-    fn print(&self) {
-        println!("object_id: {}", self.object_id);
-        println!("object_age: {}", self.object_age);
-        println!("object_prediction_age: {}", self.object_prediction_age);
-        println!("relative_timestamp: {}", self.relative_timestamp);
-        print!("reference_point: ");
-        self.reference_point.print();
-        print!("reference_point_sigma: ");
-        self.reference_point_sigma.print();
-        print!("closest_point: ");
-        self.closest_point.print();
-        print!("bounding_box_center: ");
-        self.bounding_box_center.print();
-        print!("bounding_box_size: ");
-        self.bounding_box_size.print();
-        print!("object_box_center: ");
-        self.object_box_center.print();
-        print!("object_box_size: ");
-        self.object_box_size.print();
-        println!("object_box_orientation: {}", self.object_box_orientation);
-        print!("absolute_velocity: ");
-        self.absolute_velocity.print();
-        print!("absolute_velosity_sigma: ");
-        self.absolute_velocity_sigma.print();
-        println!("number_of_contour_points: {}", self.number_of_contour_points);
-        for i in 0..self.contour_point_vec.len() {
-            self.contour_point_vec[i].print();
-        }
+    // object box orientation in 1/32 deg
+    fn orientation(&self) -> f64 {
+        (self.object_box_orientation as f64) / 32.0 * std::f64::consts::PI / 180.0
     }
 }
 
+// object data of a fusion ecu, big endian and in meters, radians and m/s
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct Point2D {
-    position_x: i16,
-    position_y: i16,
+struct FusedObjectData {
+    scan_start_timestamp: NtpTime,
+    number_of_objects: u16,
+    objects_vec: Vec<FusedObject>,
 }
 
-impl Point2D {
-    fn print(&self) {
-        println!("{}, {}", self.position_x, self.position_y);
+impl FusedObjectData {
+    // none when the payload is shorter than its object counts say
+    fn new(buffer: &[u8]) -> Option<FusedObjectData> {
+        if buffer.len() < 10 {
+            return None;
+        }
+        let mut object_data = FusedObjectData {
+            scan_start_timestamp: NtpTime {
+                secs: BigEndian::read_u32(&buffer[0..4]),
+                precise: BigEndian::read_u32(&buffer[4..8]),
+            },
+            number_of_objects: BigEndian::read_u16(&buffer[8..10]),
+            objects_vec: Vec::new(),
+        };
+        let mut offset: usize = 10;
+        for _ in 0..(object_data.number_of_objects as usize) {
+            let data_len = 132 + (*buffer.get(offset+130)? as usize) * 8;
+            object_data.objects_vec.push(FusedObject::new(buffer.get(offset..offset+data_len)?));
+            offset += data_len;
+        }
+        Some(object_data)
+    }
+
+    // in the units of lux object data, so that every output of those can
+    // take them
+    fn object_data(&self) -> ObjectData {
+        let start = self.scan_start_timestamp.unix_timestamp();
+        ObjectData {
+            scan_start_timestamp: self.scan_start_timestamp.clone(),
+            number_of_objects: self.number_of_objects,
+            objects_vec: self.objects_vec.iter().map(|object| object.object_info(start)).collect(),
+        }
     }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct FusedObject {
+    object_id: u16,
+    flags: u16,
+    object_age: u32,
+    timestamp: NtpTime,
+    object_prediction_age: u16,
+    classification: u8,
+    classification_certainty: u8,
+    classification_age: u32,
+    bounding_box_center: Point2DFloat,
+    bounding_box_size: Point2DFloat,
+    object_box_center: Point2DFloat,
+    object_box_center_sigma: Point2DFloat,
+    object_box_size: Point2DFloat,
+    object_box_orientation: f32,
+    object_box_orientation_sigma: f32,
+    relative_velocity: Point2DFloat,
+    relative_velocity_sigma: Point2DFloat,
+    absolute_velocity: Point2DFloat,
+    absolute_velocity_sigma: Point2DFloat,
+    number_of_contour_points: u8,
+    index_of_closest_point: u8,
+    contour_point_vec: Vec<Point2DFloat>,
+}
+
+impl FusedObject {
+    fn new(buffer: &[u8]) -> FusedObject {
+        let point = |offset: usize| Point2DFloat {
+            x: BigEndian::read_f32(&buffer[offset..offset+4]),
+            y: BigEndian::read_f32(&buffer[offset+4..offset+8]),
+        };
+        let number_of_contour_points = buffer[130];
+        FusedObject {
+            object_id: BigEndian::read_u16(&buffer[0..2]),
+            flags: BigEndian::read_u16(&buffer[2..4]),
+            object_age: BigEndian::read_u32(&buffer[4..8]),
+            timestamp: NtpTime {
+                secs: BigEndian::read_u32(&buffer[8..12]),
+                precise: BigEndian::read_u32(&buffer[12..16]),
+            },
+            object_prediction_age: BigEndian::read_u16(&buffer[16..18]),
+            classification: buffer[18],
+            classification_certainty: buffer[19],
+            classification_age: BigEndian::read_u32(&buffer[20..24]),
+            bounding_box_center: point(24),
+            bounding_box_size: point(32),
+            object_box_center: point(40),
+            object_box_center_sigma: point(48),
+            object_box_size: point(56),
+            object_box_orientation: BigEndian::read_f32(&buffer[72..76]),
+            object_box_orientation_sigma: BigEndian::read_f32(&buffer[76..80]),
+            relative_velocity: point(80),
+            relative_velocity_sigma: point(88),
+            absolute_velocity: point(96),
+            absolute_velocity_sigma: point(104),
+            number_of_contour_points,
+            index_of_closest_point: buffer[131],
+            contour_point_vec: (0..number_of_contour_points as usize).map(|i| point(132 + i * 8)).collect(),
+        }
+    }
+
+    // cm, cm/s and 1/32 deg, ages in scans are capped
+    fn object_info(&self, scan_start: f64) -> ObjectInfo {
+        let cm = |point: &Point2DFloat| Point2D {
+            position_x: (point.x * 100.0).round() as i16,
+            position_y: (point.y * 100.0).round() as i16,
+        };
+        let size = |point: &Point2DFloat| Size2D {
+            size_x: (point.x * 100.0).round() as u16,
+            size_y: (point.y * 100.0).round() as u16,
+        };
+        let closest_point = self
+            .contour_point_vec
+            .get(self.index_of_closest_point as usize)
+            .unwrap_or(&self.object_box_center);
+        ObjectInfo {
+            object_id: self.object_id,
+            object_age: self.object_age.min(u16::MAX as u32) as u16,
+            object_prediction_age: self.object_prediction_age,
+            relative_timestamp: ((self.timestamp.unix_timestamp() - scan_start) * 1000.0).round() as u16,
+            reference_point: cm(&self.object_box_center),
+            reference_point_sigma: cm(&self.object_box_center_sigma),
+            closest_point: cm(closest_point),
+            bounding_box_center: cm(&self.bounding_box_center),
+            bounding_box_size: size(&self.bounding_box_size),
+            object_box_center: cm(&self.object_box_center),
+            object_box_size: size(&self.object_box_size),
+            object_box_orientation: (self.object_box_orientation.to_degrees() * 32.0).round() as i16,
+            absolute_velocity: cm(&self.absolute_velocity),
+            absolute_velocity_sigma: size(&self.absolute_velocity_sigma),
+            relative_velocity: cm(&self.relative_velocity),
+            reserved1: 0,
+            reserved2: 0,
+            reserved3: 0,
+            number_of_contour_points: self.number_of_contour_points as u16,
+            contour_point_vec: self.contour_point_vec.iter().map(cm).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Point2D {
+    position_x: i16,
+    position_y: i16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct Size2D {
     size_x: u16,
     size_y: u16,
 }
 
-impl Size2D {
-    fn print(&self) {
-        println!("{}, {}", self.size_x, self.size_y);
-    }
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Point2DFloat {
+    x: f32,
+    y: f32,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct NtpTime {
     secs: u32,
     precise: u32,
@@ -430,61 +1166,163 @@ struct NtpTime {
 
 impl NtpTime {
     fn datetime(&self) -> DateTime<Utc> {
-        let standard_time: DateTime<Utc> = Utc.ymd(1900, 1, 1).and_hms(0, 0, 0);
+        let standard_time: DateTime<Utc> = Utc.with_ymd_and_hms(1900, 1, 1, 0, 0, 0).unwrap();
         let duration_secs: Duration = Duration::seconds(self.secs as i64);
-        let precise = self.precise * (pow::pow(10, 9) / pow::pow(2, 32));
+        let precise = ((self.precise as u64) * 1000000000) >> 32;
         let duration_precise: Duration = Duration::nanoseconds(precise as i64);
         let duration = duration_secs + duration_precise;
         let utc: DateTime<Utc> = standard_time + duration;
         utc
     }
 
-    fn print(&self) {
-        println!("{}, {}", self.secs, self.precise);
+    fn unix_timestamp(&self) -> f64 {
+        (self.secs as f64) - 2208988800.0 + (self.precise as f64) / 4294967296.0
     }
 
-    fn print_datetime(&self) {
-        let standard_time: DateTime<Utc> = Utc.ymd(1900, 1, 1).and_hms(0, 0, 0);
-        let duration_secs: Duration = Duration::seconds(self.secs as i64);
-        let precise = ((self.precise as f64) * (4294967296.0 / 1000000000.0)).round() as u32;
-        let duration_precise: Duration = Duration::nanoseconds(precise as i64);
-        let duration = duration_secs + duration_precise;
-        let utc: DateTime<Utc> = standard_time + duration;
-        println!("{:?}", utc);
+    fn add_micros(&self, micros: u32) -> NtpTime {
+        let time = (((self.secs as u64) << 32) | (self.precise as u64)) + ((micros as u64) << 32) / 1000000;
+        NtpTime {
+            secs: (time >> 32) as u32,
+            precise: time as u32,
+        }
+    }
+
+    // times before 1970, of a sensor that was never synced, clamp to 0
+    fn unix_nanos(&self) -> u64 {
+        match (self.secs as u64).checked_sub(2208988800) {
+            Some(secs) => secs * 1000000000 + (((self.precise as u64) * 1000000000) >> 32),
+            None => 0,
+        }
     }
 }
 
+// read timeouts and signals, after which reading is simply retried
+fn is_interrupted(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted)
+}
+
 fn receive_payload(stream: &mut std::net::TcpStream, payload_len: u32) -> Vec<u8> {
-    let mut remaining_len: u32 = payload_len;
-    let mut buffer: [u8; 1024] = [0; 1024];
-    let mut payload: Vec<u8> = Vec::new();
-    while remaining_len > 0 {
-        let mut msg_len = stream.read(&mut buffer[..]).unwrap();
-        msg_len = if msg_len > (remaining_len as usize) {remaining_len as usize} else {msg_len};
-        remaining_len = remaining_len - (msg_len as u32);
-        payload.append(&mut buffer[0..msg_len].to_vec());
+    let mut payload: Vec<u8> = vec![0; payload_len as usize];
+    let mut received = 0;
+    while received < payload.len() {
+        match stream.read(&mut payload[received..]) {
+            Ok(0) => break,
+            Ok(msg_len) => received += msg_len,
+            Err(ref e) if is_interrupted(e) => continue,
+            Err(_) => break,
+        }
     }
+    payload.truncate(received);
     payload
 }
 
-fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} FILE [options]", program);
+const COMMANDS: &str = "Commands:
+    record      record from a sensor
+    replay      serve an idc recording the way a sensor would
+    inspect     list the frames of a recording or print one in full
+    convert     convert a recording to another format
+    stats       summarize a recording
+    param       get or set a sensor parameter
+    sync-time   set the sensor clock to the time of this computer
+    repair      close recordings that were not closed properly
+    view        show the live scans and objects from above, or scrub through a recording
+";
+
+fn print_commands(program: &str) {
+    println!("Usage: {} COMMAND [options]\n", program);
+    print!("{}", COMMANDS);
+    println!("\nRun '{} COMMAND --help' for the options of a command.", program);
+}
+
+fn print_usage(program: &str, usage: &str, opts: &Options) {
+    let brief = format!("Usage: {} {}", program, usage);
     print!("{}", opts.usage(&brief));
 }
 
+// usage errors exit with status 2, every other failure with 1
+#[derive(Debug)]
+enum Error {
+    Usage(String),
+    Failure(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Failure(error.to_string())
+    }
+}
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(error: parquet::errors::ParquetError) -> Error {
+        Error::Failure(error.to_string())
+    }
+}
+
+fn failure<'a, E: fmt::Display>(context: &'a str) -> impl FnOnce(E) -> Error + 'a {
+    move |error| Error::Failure(format!("{}: {}", context, error))
+}
+
+fn required(matches: &Matches, name: &str) -> Result<String, Error> {
+    let option = if name.len() == 1 { format!("-{}", name) } else { format!("--{}", name) };
+    matches.opt_str(name).ok_or_else(|| Error::Usage(format!("missing required option {}", option)))
+}
+
+fn value<T: FromStr>(matches: &Matches, name: &str) -> Result<Option<T>, Error> {
+    parse_with(matches, name, |text| text.parse().ok())
+}
+
+fn parse_with<T, F: Fn(&str) -> Option<T>>(matches: &Matches, name: &str, parse: F) -> Result<Option<T>, Error> {
+    match matches.opt_str(name) {
+        Some(text) => match parse(&text) {
+            Some(value) => Ok(Some(value)),
+            None => Err(Error::Usage(format!("invalid value '{}' for --{}", text, name))),
+        },
+        None => Ok(None),
+    }
+}
+
+// decimal or 0x prefixed hexadecimal
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// the single FILE argument of a command
+fn input(matches: &Matches) -> Result<&str, Error> {
+    match matches.free.len() {
+        0 => Err(Error::Usage("missing input FILE".to_string())),
+        1 => Ok(&matches.free[0]),
+        _ => Err(Error::Usage(format!("unexpected argument '{}'", matches.free[1]))),
+    }
+}
+
+fn address(matches: &Matches) -> String {
+    matches.opt_str("address").unwrap_or_else(|| SENSOR_ADDRESS.to_string())
+}
+
+fn connect(address: &str) -> Result<TcpStream, Error> {
+    TcpStream::connect(address).map_err(failure(&format!("cannot connect to {}", address)))
+}
+
+// seconds since the GPS epoch (1980-01-06), with the 18 leap seconds since then
+fn gps_time(unix_timestamp: f64) -> f64 {
+    unix_timestamp - 315964800.0 + 18.0
+}
+
 fn ntp_time_now() -> u64 {
     let utc: DateTime<Utc> = Utc::now();
-    let standard_time: DateTime<Utc> = Utc.ymd(1900, 1, 1).and_hms(0, 0, 0);
+    let standard_time: DateTime<Utc> = Utc.with_ymd_and_hms(1900, 1, 1, 0, 0, 0).unwrap();
     let diff = utc.signed_duration_since(standard_time);
     let mut buffer: [u8; 8] = [0; 8];
-    print!("{:?}", pow::pow(2, 32));
     let precise = ((diff.num_nanoseconds().unwrap() as f64) * (4294967296.0 / 1000000000.0)).round() as u32;
     BigEndian::write_u32(&mut buffer[0..4], diff.num_seconds() as u32);
-    BigEndian::write_u32(&mut buffer[4..8], precise as u32);
+    BigEndian::write_u32(&mut buffer[4..8], precise);
     BigEndian::read_u64(&buffer)
 }
 
-fn sync_time(stream: &mut TcpStream) {
+fn sync_time(stream: &mut TcpStream) -> io::Result<()> {
     let mut buffer: [u8; 34] = [0; 34];
 
     let header: Header = Header {
@@ -510,72 +1348,845 @@ fn sync_time(stream: &mut TcpStream) {
     LittleEndian::write_u32(&mut buffer[24..28], 0x0030);
     LittleEndian::write_u16(&mut buffer[28..30], 0);
     LittleEndian::write_u32(&mut buffer[30..34], sec);
-    stream.write(&buffer);
+    stream.write_all(&buffer)?;
 
     LittleEndian::write_u32(&mut buffer[24..28], 0x0031);
     LittleEndian::write_u16(&mut buffer[28..30], 0);
     LittleEndian::write_u32(&mut buffer[30..34], precise);
-    stream.write(&buffer);
+    stream.write_all(&buffer)
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let program = args[0].clone();
-    let mut opts = Options::new();
-    opts.optopt("o", "", "set output file name", "NAME");
-    opts.optflag("h", "help", "print this help menu");
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
+fn send_command(stream: &mut TcpStream, payload: &[u8]) -> io::Result<()> {
+    let header: Header = Header {
+        magic_word: 0xaffec0c2,
+        size_of_previous_messages: 0,
+        size_of_message_data: payload.len() as u32,
+        reserved: 0,
+        device_id: 1,
+        data_type: DataType::Command,
+        ntp_time: NtpTime {
+            secs: 0,
+            precise: 0,
+        },
     };
-    if matches.opt_present("h") {
-        print_usage(&program, opts);
-        return;
-    }
-    let output = matches.opt_str("o").unwrap();
-
-    let mut stream = TcpStream::connect("192.168.0.1:12002").unwrap();
-    sync_time(&mut stream);
-    let mut buffer: [u8; 24] = [0; 24];
-    let mut file = BufWriter::new(File::create(output).unwrap());
-    loop {
-        let msg_len = stream.read(&mut buffer[..]).unwrap();
-        if msg_len == 24 {
-            let header = Header::new(buffer);
-            if header.magic_word == 0xaffec0c2 {
-                let mut payload: Vec<u8> = receive_payload(&mut stream, header.size_of_message_data);
-                // println!("payload ");
-                // for i in 0..(h.size_of_message_data as usize) {
-                //     print!("{:02x}", payload[i]);
-                // }
-                // println!("");
-                match header.data_type {
-                    DataType::Command => {
-                        // header.print();
-                    },
-                    DataType::CommandReply => {
-                        // header.print();
-                    },
-                    DataType::Error => {
-                        // header.print();
-                    },
-                    DataType::ScanData => {
-                        // header.print();
-                        // let scan_data = ScanData::new(payload.as_slice());
-                    },
-                    DataType::ObjectData => {
-                        header.print();
-                        let object_data = ObjectData::new(payload.as_slice());
-                        // object_data.print();
-                        let s_object_data = serde_yaml::to_string(&object_data).unwrap();
-                        file.write(s_object_data.as_bytes()).unwrap();
-                        file.write(b"\n").unwrap();
+    let mut buffer = vec![0; 24 + payload.len()];
+    header.encode(&mut buffer[0..24]);
+    buffer[24..].copy_from_slice(payload);
+    stream.write_all(&buffer)
+}
+
+// skips the measurement data the sensor keeps sending until the reply to
+// `command` arrives
+fn receive_reply(stream: &mut TcpStream, command: u16) -> io::Result<Vec<u8>> {
+    let timeout = ::std::time::Duration::from_secs(2);
+    let started = Instant::now();
+    stream.set_read_timeout(Some(timeout))?;
+    for (header, payload) in IdcReader::new(&*stream) {
+        if header.data_type == DataType::CommandReply && payload.len() >= 2 {
+            let reply = LittleEndian::read_u16(&payload[0..2]);
+            if reply & 0x7fff == command {
+                if reply & 0x8000 != 0 {
+                    return Err(io::Error::other("the sensor rejected the command"));
+                }
+                return Ok(payload);
+            }
+        }
+        if started.elapsed() >= timeout {
+            break;
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "no reply from the sensor"))
+}
+
+fn stop_measure(stream: &mut TcpStream) -> io::Result<()> {
+    let mut payload: [u8; 4] = [0; 4];
+    LittleEndian::write_u16(&mut payload[0..2], 0x0021);
+    send_command(stream, &payload)
+}
+
+fn get_parameter(stream: &mut TcpStream, index: u16) -> io::Result<u32> {
+    let mut payload: [u8; 6] = [0; 6];
+    LittleEndian::write_u16(&mut payload[0..2], 0x0011);
+    LittleEndian::write_u16(&mut payload[4..6], index);
+    send_command(stream, &payload)?;
+    let reply = receive_reply(stream, 0x0011)?;
+    if reply.len() < 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "short reply from the sensor"));
+    }
+    Ok(LittleEndian::read_u32(&reply[4..8]))
+}
+
+fn set_parameter(stream: &mut TcpStream, index: u16, value: u32) -> io::Result<()> {
+    let mut payload: [u8; 10] = [0; 10];
+    LittleEndian::write_u16(&mut payload[0..2], 0x0010);
+    LittleEndian::write_u16(&mut payload[4..6], index);
+    LittleEndian::write_u32(&mut payload[6..10], value);
+    send_command(stream, &payload)?;
+    receive_reply(stream, 0x0010).map(|_| ())
+}
+
+fn open_recording(path: &str) -> Result<Box<dyn Iterator<Item = io::Result<Record>>>, Error> {
+    let reader = compression::open(Path::new(path)).map_err(failure(&format!("cannot open {}", path)))?;
+    Ok(match output::format_from_path(Path::new(path)) {
+        Some(format) => match Format::from_name(format) {
+            Some(format) => Box::new(StreamReader::new(reader, format)),
+            None => return Err(Error::Usage(format!("cannot read {} recordings, only idc, yaml and ndjson", format))),
+        },
+        None => Box::new(IdcReader::new(reader).map(|(header, payload)| Ok(Record::new(header, &payload)))),
+    })
+}
+
+fn rotation_options(opts: &mut Options) {
+    opts.optopt("", "rotate-size", "start a new output file every MB megabytes", "MB");
+    opts.optopt("", "rotate-minutes", "start a new output file every MINUTES minutes", "MINUTES");
+    opts.optopt("", "rotate-scans", "start a new output file every N scans", "N");
+}
+
+fn rotation(matches: &Matches, defaults: Rotation) -> Result<Rotation, Error> {
+    Ok(Rotation {
+        max_bytes: parse_with(matches, "rotate-size", |text| text.parse::<u64>().ok()?.checked_mul(1024 * 1024))?
+            .or(defaults.max_bytes),
+        max_seconds: value::<f64>(matches, "rotate-minutes")?.map(|minutes| minutes * 60.0).or(defaults.max_seconds),
+        max_scans: value(matches, "rotate-scans")?.or(defaults.max_scans),
+    })
+}
+
+// the files converted from the recording, or from one of its devices
+struct Conversion {
+    dir: PathBuf,
+    las_writer: Option<LasWriter>,
+    kitti_writer: Option<KittiWriter>,
+    csv_writer: Option<ObjectCsvWriter>,
+    parquet_writer: Option<ParquetWriter>,
+}
+
+impl Conversion {
+    fn create(dir: &Path, format: &str, matches: &Matches) -> Result<Conversion, Error> {
+        let output = dir.display().to_string();
+        let create_dir = |dir: &Path| fs::create_dir_all(dir).map_err(failure(&format!("cannot create {}", output)));
+        let mut conversion = Conversion {
+            dir: dir.to_path_buf(),
+            las_writer: None,
+            kitti_writer: None,
+            csv_writer: None,
+            parquet_writer: None,
+        };
+        match format {
+            "las" if matches.opt_present("accumulate") => {
+                conversion.las_writer = Some(LasWriter::create(dir).map_err(failure(&output))?);
+            },
+            "pcd" | "ply" | "las" => create_dir(dir)?,
+            "kitti" => {
+                conversion.kitti_writer = Some(KittiWriter::create(dir, matches.opt_present("labels")).map_err(failure(&output))?);
+            },
+            "csv" => conversion.csv_writer = Some(ObjectCsvWriter::create(dir).map_err(failure(&output))?),
+            "parquet" => {
+                create_dir(dir)?;
+                conversion.parquet_writer = Some(ParquetWriter::create(dir)?);
+            },
+            _ => {},
+        }
+        Ok(conversion)
+    }
+
+    fn write(&mut self, record: &Record, format: &str, binary: bool, pose: Option<&MovementData>) -> Result<(), Error> {
+        let dir = self.dir.as_path();
+        if let Some(scan) = record.message.scan() {
+            match format {
+                "pcd" => pcd::write_scan(dir, &scan, binary)?,
+                "ply" => ply::write_scan(dir, &scan, binary)?,
+                "las" => match self.las_writer {
+                    Some(ref mut writer) => writer.write_scan(&scan, pose)?,
+                    None => {
+                        let mut writer = LasWriter::create(&dir.join(scan.file_name("las")))?;
+                        writer.write_scan(&scan, None)?;
+                        writer.finish()?;
                     },
-                    DataType::MovementData => {},
-                    DataType::EgoMotionData => {},
-                    DataType::SensorInfo => {},
+                },
+                "kitti" => self.kitti_writer.as_mut().unwrap().write_scan(&scan)?,
+                "parquet" => self.parquet_writer.as_mut().unwrap().write_scan(&record.header, &scan)?,
+                _ => {},
+            }
+        } else if let Some(object_data) = record.message.object_data() {
+            if let Some(ref mut writer) = self.kitti_writer {
+                writer.write_objects(&object_data)?;
+            }
+            if let Some(ref mut writer) = self.csv_writer {
+                writer.write_objects(&record.header, &object_data)?;
+            }
+            if let Some(ref mut writer) = self.parquet_writer {
+                writer.write_objects(&record.header, &object_data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if let Some(writer) = self.las_writer {
+            writer.finish()?;
+        }
+        if let Some(mut writer) = self.csv_writer {
+            writer.flush()?;
+        }
+        if let Some(writer) = self.parquet_writer {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn convert(matches: &Matches) -> Result<(), Error> {
+    let input = input(matches)?;
+    let output = required(matches, "o")?;
+    let format = required(matches, "to")?;
+    let binary = matches.opt_present("binary");
+    let ego_motion = matches.opt_present("ego-motion");
+    if ego_motion && !(format == "las" && matches.opt_present("accumulate")) {
+        return Err(Error::Usage("--ego-motion only applies to --to las --accumulate".to_string()));
+    }
+    let split_devices = matches.opt_present("split-devices");
+    let rotation = rotation(matches, Rotation::default())?;
+    let dir = Path::new(&output);
+    let mut record_writer = None;
+    match format.as_str() {
+        "pcd" | "ply" | "las" | "kitti" | "csv" | "parquet" => {},
+        "yaml" | "ndjson" | "mcap" | "bag" => {
+            record_writer = Some(RotatingWriter::new(dir, &format, rotation));
+        },
+        _ => {
+            return Err(Error::Usage(format!(
+                "unknown format '{}' for --to, expected pcd, ply, las, kitti, csv, parquet, yaml, ndjson, mcap or bag",
+                format
+            )))
+        },
+    }
+    // labelled records go to a single output, the other formats get one for
+    // each device, e.g. "out_device2"
+    let mut conversions: BTreeMap<String, Conversion> = BTreeMap::new();
+    if !split_devices && record_writer.is_none() {
+        conversions.insert(String::new(), Conversion::create(dir, &format, matches)?);
+    }
+    let mut movement_data: Option<MovementData> = None;
+    for record in open_recording(input)? {
+        let mut record = record.map_err(failure(input))?;
+        if split_devices {
+            record.label_device();
+        }
+        if let Some(ref mut writer) = record_writer {
+            writer.write(&record)?;
+            continue;
+        }
+        if let Message::MovementData(data) = record.message {
+            movement_data = Some(data);
+            continue;
+        }
+        // devices without scans or objects get no output
+        if !record.data_type.is_scan() && record.message.object_data().is_none() {
+            continue;
+        }
+        let device = match record.source {
+            Some(ref source) if split_devices => source.replace('/', "_"),
+            _ => String::new(),
+        };
+        if !conversions.contains_key(&device) {
+            let path = if device.is_empty() { dir.to_path_buf() } else { sensor_path(dir, &device) };
+            conversions.insert(device.clone(), Conversion::create(&path, &format, matches)?);
+        }
+        let pose = if ego_motion { movement_data.as_ref() } else { None };
+        conversions.get_mut(&device).unwrap().write(&record, &format, binary, pose)?;
+    }
+    for (_, conversion) in conversions {
+        conversion.finish()?;
+    }
+    if let Some(writer) = record_writer {
+        Box::new(writer).finish()?;
+    }
+    Ok(())
+}
+
+// "drive.ndjson.zst" is "drive_front.ndjson.zst" for the sensor "front"
+fn sensor_path(path: &Path, name: &str) -> PathBuf {
+    let file_name = path.file_name().and_then(OsStr::to_str).unwrap_or("lux");
+    let (stem, extensions) = file_name.split_at(file_name.find('.').unwrap_or(file_name.len()));
+    path.with_file_name(format!("{}_{}{}", stem, name, extensions))
+}
+
+// the sensors of the profile, replaced by those given on the command line
+fn sensors(matches: &Matches, config: Vec<Sensor>) -> Result<Vec<Sensor>, Error> {
+    let addresses = matches.opt_strs("address");
+    let mut sensors = if addresses.is_empty() || (addresses.len() == 1 && config.len() == 1) {
+        config
+    } else {
+        Vec::new()
+    };
+    if sensors.is_empty() {
+        sensors.push(Sensor {
+            name: String::new(),
+            address: SENSOR_ADDRESS.to_string(),
+            mounting_pose: None,
+        });
+    }
+    if !addresses.is_empty() {
+        sensors.resize(addresses.len(), sensors[0].clone());
+        for (sensor, address) in sensors.iter_mut().zip(addresses) {
+            sensor.address = address;
+        }
+    }
+    let poses = matches.opt_strs("mounting-pose");
+    if !poses.is_empty() {
+        if poses.len() != sensors.len() {
+            return Err(Error::Usage("--mounting-pose must be given once for every sensor".to_string()));
+        }
+        for (sensor, pose) in sensors.iter_mut().zip(poses) {
+            match MountingPose::parse(&pose) {
+                Some(pose) => sensor.mounting_pose = Some(pose),
+                None => return Err(Error::Usage(format!("invalid value '{}' for --mounting-pose", pose))),
+            }
+        }
+    }
+    for index in 0..sensors.len() {
+        if sensors[index].name.is_empty() {
+            sensors[index].name = format!("sensor{}", index + 1);
+        }
+        if sensors[..index].iter().any(|sensor| sensor.name == sensors[index].name) {
+            return Err(Error::Failure(format!("sensors: the name '{}' is used twice", sensors[index].name)));
+        }
+    }
+    Ok(sensors)
+}
+
+fn record(matches: &Matches) -> Result<(), Error> {
+    if let Some(argument) = matches.free.first() {
+        return Err(Error::Usage(format!("unexpected argument '{}'", argument)));
+    }
+    let config = match matches.opt_str("config") {
+        Some(path) => Config::load(Path::new(&path)).map_err(failure(&path))?,
+        None => Config::default(),
+    };
+    let sensors = sensors(matches, config.sensors)?;
+    let output = match matches.opt_str("o").or(config.output.path) {
+        Some(output) => output,
+        None => return Err(Error::Usage("missing required option -o".to_string())),
+    };
+    match output::format_from_path(Path::new(&output)) {
+        Some("bag") if compression::Compression::from_path(Path::new(&output)).is_some() => {
+            return Err(Error::Usage("bag files cannot be compressed".to_string()));
+        },
+        Some(_) => {},
+        None => {
+            return Err(Error::Usage(format!(
+                "unknown output format for {}, expected .yaml, .ndjson, .mcap or .bag",
+                output
+            )))
+        },
+    }
+    let selections = match matches.opt_str("record") {
+        Some(record) => select::parse(&record).map_err(Error::Usage)?,
+        None => config.output.record.unwrap_or_else(|| select::parse("object").unwrap()),
+    };
+    let rotation = rotation(matches, config.rotation.rotation())?;
+    let flush_interval = value(matches, "flush-interval")?.or(config.output.flush_interval).unwrap_or(1.0);
+    let fsync = matches.opt_present("fsync") || config.output.fsync;
+    let stop_measure_at_end = matches.opt_present("stop-measure") || config.output.stop_measure;
+    let per_sensor = matches.opt_present("per-sensor") || config.output.per_sensor;
+    let split_devices = matches.opt_present("split-devices") || config.output.split_devices;
+    let time_sync_interval = value::<f64>(matches, "time-sync-interval")?.or(config.time_sync.interval);
+    let foxglove_listen = matches.opt_str("foxglove").or(config.foxglove.listen);
+    let filters = config.filters;
+    let mut window = Window::default();
+    window.start_at = parse_with(matches, "start-at", window::parse_time)?.or(filters.start_at);
+    window.start_objects = value(matches, "start-objects")?.or(filters.start_objects);
+    window.duration = value(matches, "duration")?.or(filters.duration);
+    window.max_scans = value(matches, "max-scans")?.or(filters.max_scans);
+    window.max_bytes = parse_with(matches, "max-bytes", window::parse_bytes)?.or(filters.max_bytes);
+    window.until = parse_with(matches, "until", window::parse_time)?.or(filters.until);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst))
+        .map_err(failure("cannot install the signal handler"))?;
+
+    let server = match foxglove_listen {
+        Some(ref listen) => Some(Server::start(listen).map_err(failure(&format!("cannot listen on {}", listen)))?),
+        None => None,
+    };
+    let mut streams = Vec::new();
+    for sensor in &sensors {
+        let stream = connect(&sensor.address)?;
+        // wake up regularly to notice a shutdown request
+        stream.set_read_timeout(Some(::std::time::Duration::from_millis(200)))?;
+        streams.push(stream);
+    }
+    // a common output, or one for each sensor with its own manifest
+    let mut writers: Vec<ManifestWriter> = if per_sensor && sensors.len() > 1 {
+        sensors
+            .iter()
+            .map(|sensor| {
+                let writer = SplitWriter::new(&sensor_path(Path::new(&output), &sensor.name), &selections, rotation);
+                ManifestWriter::new(writer, ::std::slice::from_ref(sensor), split_devices)
+            })
+            .collect()
+    } else {
+        vec![ManifestWriter::new(SplitWriter::new(Path::new(&output), &selections, rotation), &sensors, split_devices)]
+    };
+    let mut dashboard = Dashboard::new(&sensors, split_devices, matches.opt_present("quiet"));
+    if window.start_at.is_some() || window.start_objects.is_some() {
+        dashboard.set_state("waiting for the start trigger");
+    }
+    if let Some(ref listen) = foxglove_listen {
+        dashboard.log(&format!("foxglove websocket server on ws://{}", listen));
+    }
+
+    let (sender, events) = mpsc::channel();
+    let mut threads = Vec::new();
+    for (index, stream) in streams.into_iter().enumerate() {
+        let connection = Connection {
+            index,
+            // records are only tagged when they need to be told apart
+            source: if sensors.len() > 1 { Some(sensors[index].name.clone()) } else { None },
+            split_devices,
+            stream,
+            time_sync_interval,
+            stop_measure: stop_measure_at_end,
+        };
+        threads.push(connection.spawn(running.clone(), sender.clone()));
+    }
+    drop(sender);
+    let bytes = |writers: &[ManifestWriter]| writers.iter().map(ManifestWriter::bytes).sum::<u64>();
+    let log = |dashboard: &mut Dashboard, index: usize, message: &str| {
+        if sensors.len() > 1 {
+            dashboard.log(&format!("{}: {}", sensors[index].name, message));
+        } else {
+            dashboard.log(message);
+        }
+    };
+    let mut state = "interrupted".to_string();
+    let mut open = sensors.len();
+    let mut last_flush = Instant::now();
+    while running.load(Ordering::SeqCst) {
+        match events.recv_timeout(::std::time::Duration::from_millis(200)) {
+            Ok(Event::Record(index, record)) => {
+                dashboard.add(index, &record);
+                if let Some(ref server) = server {
+                    server.publish(&record);
+                }
+                // the common output is the only one
+                let output_index = index.min(writers.len() - 1);
+                let writer = &mut writers[output_index];
+                writer.observe(&record);
+                let started = window.is_started();
+                if window.accept(&record) && writer.accepts(&record.data_type) {
+                    writer.write(&record).map_err(failure(&output))?;
+                }
+                if !started && window.is_started() {
+                    dashboard.log("recording started");
+                    dashboard.set_state("recording");
+                }
+            },
+            Ok(Event::Log(index, message)) => log(&mut dashboard, index, &message),
+            Ok(Event::Closed(index, reason)) => {
+                if sensors.len() > 1 {
+                    log(&mut dashboard, index, &reason);
+                }
+                dashboard.set_closed(index, &reason);
+                open -= 1;
+                if open == 0 {
+                    state = reason;
+                    break;
                 }
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {},
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                state = "all sensor connections ended".to_string();
+                break;
+            },
+        }
+        if last_flush.elapsed().as_secs_f64() >= flush_interval {
+            for writer in &mut writers {
+                writer.flush(fsync).map_err(failure(&output))?;
+            }
+            last_flush = Instant::now();
+        }
+        if let Some(ref server) = server {
+            for message in server.logs() {
+                dashboard.log(&message);
             }
         }
+        if dashboard.is_due() {
+            dashboard.refresh(bytes(&writers));
+            if let Some(ref server) = server {
+                server.publish_diagnostics(&dashboard.health());
+            }
+        }
+        let total = if window.max_bytes.is_some() { bytes(&writers) } else { 0 };
+        if let Some(reason) = window.stop_reason(total) {
+            state = reason.to_string();
+            break;
+        }
+    }
+    dashboard.finish(&state, bytes(&writers));
+
+    // the connections send StopMeasure when they end
+    running.store(false, Ordering::SeqCst);
+    for thread in threads {
+        let _ = thread.join();
+    }
+    for event in events.try_iter() {
+        if let Event::Log(index, message) = event {
+            log(&mut dashboard, index, &message);
+        }
+    }
+    println!("recording stopped: {}", state);
+    for writer in writers {
+        writer.print_summary();
+        Box::new(writer).finish().map_err(failure(&output))?;
+    }
+    Ok(())
+}
+
+fn replay(matches: &Matches) -> Result<(), Error> {
+    let input = input(matches)?;
+    let listen = matches.opt_str("listen").unwrap_or_else(|| "0.0.0.0:12002".to_string());
+    let speed = value(matches, "speed")?.unwrap_or(1.0);
+    if speed < 0.0 {
+        return Err(Error::Usage(format!("invalid value '{}' for --speed", speed)));
+    }
+    replay::replay(Path::new(input), &listen, speed, matches.opt_present("loop")).map_err(failure(input))
+}
+
+fn inspect(matches: &Matches) -> Result<(), Error> {
+    let input = input(matches)?;
+    let hex = matches.opt_present("hex");
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = match value(matches, "frame")? {
+        Some(index) => inspect::print_frame(&mut out, Path::new(input), index, hex),
+        None => inspect::list(&mut out, Path::new(input), hex),
+    };
+    match result {
+        // piped into head or less
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(failure(input)),
+    }
+}
+
+fn stats(matches: &Matches) -> Result<(), Error> {
+    let input = input(matches)?;
+    let mut stats = Stats::new(value(matches, "gap")?.unwrap_or(manifest::GAP_SECONDS));
+    for record in open_recording(input)? {
+        stats.add(&record.map_err(failure(input))?);
+    }
+    let report = stats.report();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = if matches.opt_present("json") {
+        serde_json::to_writer_pretty(&mut out, &report).map_err(io::Error::from).and_then(|_| writeln!(out))
+    } else {
+        report.write_table(&mut out)
+    };
+    match result {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(failure(input)),
+    }
+}
+
+fn param(matches: &Matches) -> Result<(), Error> {
+    let arguments: Vec<&str> = matches.free.iter().map(String::as_str).collect();
+    let (index, value) = match arguments.as_slice() {
+        ["get", index] => (index, None),
+        ["set", index, value] => {
+            let value = parse_number(value).ok_or_else(|| Error::Usage(format!("invalid parameter value '{}'", value)))?;
+            (index, Some(value))
+        },
+        _ => return Err(Error::Usage("expected 'get INDEX' or 'set INDEX VALUE'".to_string())),
+    };
+    let index = parse_number(index)
+        .filter(|&index| index <= 0xffff)
+        .ok_or_else(|| Error::Usage(format!("invalid parameter index '{}'", index)))? as u16;
+    let mut stream = connect(&address(matches))?;
+    match value {
+        Some(value) => {
+            set_parameter(&mut stream, index, value).map_err(failure(&format!("cannot set parameter 0x{:04x}", index)))?;
+            println!("0x{:04x} = {} (0x{:08x})", index, value, value);
+        },
+        None => {
+            let value =
+                get_parameter(&mut stream, index).map_err(failure(&format!("cannot get parameter 0x{:04x}", index)))?;
+            println!("0x{:04x} = {} (0x{:08x})", index, value, value);
+        },
+    }
+    Ok(())
+}
+
+fn sync_time_command(matches: &Matches) -> Result<(), Error> {
+    if let Some(argument) = matches.free.first() {
+        return Err(Error::Usage(format!("unexpected argument '{}'", argument)));
+    }
+    let address = address(matches);
+    let mut stream = connect(&address)?;
+    sync_time(&mut stream).map_err(failure(&address))?;
+    println!("sent the time to {}", address);
+    Ok(())
+}
+
+fn view(matches: &Matches) -> Result<(), Error> {
+    if matches.free.is_empty() {
+        let address = address(matches);
+        let stream = connect(&address)?;
+        return view::live(&address, stream).map_err(failure(&address));
+    }
+    if matches.opt_present("address") {
+        return Err(Error::Usage("--address cannot be used with a FILE".to_string()));
+    }
+    let input = input(matches)?;
+    view::recording(input, open_recording(input)?).map_err(failure(input))
+}
+
+fn repair(matches: &Matches) -> Result<(), Error> {
+    if matches.free.is_empty() {
+        return Err(Error::Usage("missing input FILE".to_string()));
+    }
+    let mut failed = 0;
+    for input in &matches.free {
+        match repair::repair(Path::new(input)) {
+            Ok(true) => println!("{}: repaired", input),
+            Ok(false) => println!("{}: ok", input),
+            Err(e) => {
+                eprintln!("{}: {}", input, e);
+                failed += 1;
+            },
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(Error::Failure(format!("{} of {} files could not be repaired", failed, matches.free.len()))),
     }
-}
\ No newline at end of file
+}
+
+// the usage line and options of every command
+fn options(command: &str) -> Option<(&'static str, Options)> {
+    let mut opts = Options::new();
+    let usage = match command {
+        "record" => {
+            opts.optopt("o", "", "set output file name, compressed when it ends in .zst or .gz", "NAME");
+            opts.optopt("", "config", "read the recording profile from a yaml FILE, options given here override it", "FILE");
+            opts.optmulti("", "address", "sensor address, repeated to record from several sensors (default 192.168.0.1:12002)", "HOST:PORT");
+            opts.optopt("", "time-sync-interval", "sync the sensor time every SECONDS seconds (default only at the start)", "SECONDS");
+            rotation_options(&mut opts);
+            opts.optmulti("", "mounting-pose", "sensor mounting pose for the manifest in meters and degrees, once for every sensor", "X,Y,Z,YAW,PITCH,ROLL");
+            opts.optopt("", "flush-interval", "flush the output every SECONDS seconds (default 1), compressed output once 256 kB are buffered", "SECONDS");
+            opts.optflag("", "fsync", "also sync the output to disk on every flush");
+            opts.optflag("", "per-sensor", "write a file for each sensor, named NAME_SENSOR, instead of a common one");
+            opts.optflag("", "split-devices", "label the data by device id, e.g. of the scanners behind a fusion ecu");
+            opts.optflag("", "stop-measure", "send StopMeasure to the sensor when recording stops");
+            opts.optflag("", "quiet", "do not show the live status while recording");
+            opts.optopt("", "foxglove", "serve the live data to foxglove studio over a websocket on ADDRESS, e.g. 0.0.0.0:8765", "ADDRESS");
+            opts.optopt("", "record", "data types to record, optionally with their own format, e.g. scan:mcap,object (default object)", "TYPES");
+            opts.optopt("", "duration", "stop recording after SECONDS seconds", "SECONDS");
+            opts.optopt("", "max-scans", "stop recording after N scans", "N");
+            opts.optopt("", "max-bytes", "stop recording once the output reaches SIZE, e.g. 200M", "SIZE");
+            opts.optopt("", "until", "stop recording at TIME (rfc 3339 or local HH:MM[:SS])", "TIME");
+            opts.optopt("", "start-at", "start recording at TIME (rfc 3339 or local HH:MM[:SS])", "TIME");
+            opts.optopt("", "start-objects", "start recording at the first object data with at least N objects", "N");
+            "record [-o NAME] [--config FILE] [options]"
+        },
+        "replay" => {
+            opts.optopt("", "listen", "address to serve on (default 0.0.0.0:12002)", "HOST:PORT");
+            opts.optopt("", "speed", "replay speed factor, 0 for as fast as possible (default 1)", "FACTOR");
+            opts.optflag("", "loop", "start over at the end of the recording");
+            "replay FILE [options]"
+        },
+        "inspect" => {
+            opts.optopt("", "frame", "print frame N in full instead of listing the frames", "N");
+            opts.optflag("", "hex", "hex dump payloads without a decoder, or the payload of the printed frame");
+            "inspect FILE [options]"
+        },
+        "convert" => {
+            opts.optopt("o", "", "set output file or directory name", "NAME");
+            opts.optopt("", "to", "convert to FORMAT (pcd, ply, las, kitti, csv, parquet, yaml, ndjson, mcap, bag)", "FORMAT");
+            opts.optflag("", "binary", "write binary instead of ascii files");
+            opts.optflag("", "accumulate", "write all scans into a single las file");
+            opts.optflag("", "ego-motion", "correct accumulated scans with the vehicle movement data");
+            opts.optflag("", "labels", "write kitti label files from the object data");
+            opts.optflag("", "split-devices", "label the data by device id, e.g. of the scanners behind a fusion ecu, with an output NAME_deviceN for each where the format has no topics");
+            rotation_options(&mut opts);
+            "convert --to FORMAT -o NAME FILE [options]"
+        },
+        "stats" => {
+            opts.optflag("", "json", "print the summary as json");
+            opts.optopt("", "gap", "report pauses between messages longer than SECONDS (default 0.5)", "SECONDS");
+            "stats FILE [options]"
+        },
+        "param" => {
+            opts.optopt("", "address", "sensor address (default 192.168.0.1:12002)", "HOST:PORT");
+            "param [options] get INDEX | set INDEX VALUE"
+        },
+        "sync-time" => {
+            opts.optopt("", "address", "sensor address (default 192.168.0.1:12002)", "HOST:PORT");
+            "sync-time [options]"
+        },
+        "repair" => "repair FILE...",
+        "view" => {
+            opts.optopt("", "address", "sensor address without FILE (default 192.168.0.1:12002)", "HOST:PORT");
+            "view [FILE] [options]"
+        },
+        _ => return None,
+    };
+    opts.optflag("h", "help", "print this help menu");
+    Some((usage, opts))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let (command, arguments) = match args.get(1).map(String::as_str) {
+        Some("-h") | Some("--help") | Some("help") => {
+            print_commands(&program);
+            return;
+        },
+        // options without a command record, as before there were commands
+        Some(argument) if argument.starts_with('-') => ("record", &args[1..]),
+        Some(command) => (command, &args[2..]),
+        None => {
+            print_commands(&program);
+            process::exit(2);
+        },
+    };
+    let (usage, opts) = match options(command) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}: unknown command '{}'\n", program, command);
+            eprint!("{}", COMMANDS);
+            process::exit(2);
+        },
+    };
+    let result = match opts.parse(arguments) {
+        Ok(ref matches) if matches.opt_present("h") => {
+            print_usage(&program, usage, &opts);
+            return;
+        },
+        Ok(matches) => match command {
+            "record" => record(&matches),
+            "replay" => replay(&matches),
+            "inspect" => inspect(&matches),
+            "convert" => convert(&matches),
+            "stats" => stats(&matches),
+            "param" => param(&matches),
+            "sync-time" => sync_time_command(&matches),
+            "view" => view(&matches),
+            _ => repair(&matches),
+        },
+        Err(f) => Err(Error::Usage(f.to_string())),
+    };
+    match result {
+        Ok(()) => {},
+        Err(Error::Usage(message)) => {
+            eprintln!("{} {}: {}", program, command, message);
+            eprintln!("Usage: {} {}", program, usage);
+            process::exit(2);
+        },
+        Err(Error::Failure(message)) => {
+            eprintln!("{} {}: {}", program, command, message);
+            process::exit(1);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    use fixtures;
+    use stream::Message;
+    use DataType;
+
+    #[test]
+    fn float_scan() {
+        let mut payload = Vec::new();
+        // start time, end time offset, flags, scan number, points, scanner infos
+        payload.write_u32::<BigEndian>(3800000000).unwrap();
+        payload.write_u32::<BigEndian>(0).unwrap();
+        payload.write_u32::<BigEndian>(80000).unwrap();
+        payload.write_u32::<BigEndian>(0).unwrap();
+        payload.write_u16::<BigEndian>(7).unwrap();
+        payload.write_u16::<BigEndian>(1).unwrap();
+        payload.write_u8(1).unwrap();
+        payload.extend_from_slice(&[0; 3]);
+        // scanner info of device 2
+        payload.write_u8(2).unwrap();
+        payload.extend_from_slice(&[0; 147]);
+        // x, y, z, echo pulse width, device, layer, echo, time offset, flags
+        for value in [3.0, 4.0, 0.5, 0.25].iter() {
+            payload.write_f32::<BigEndian>(*value).unwrap();
+        }
+        payload.extend_from_slice(&[2, 1, 0, 0]);
+        payload.write_u32::<BigEndian>(1000).unwrap();
+        payload.write_u16::<BigEndian>(0x1234).unwrap();
+        payload.write_u16::<BigEndian>(0).unwrap();
+
+        let record = fixtures::record(0x2209, &payload);
+        assert_eq!(record.data_type, DataType::FloatScanData);
+        let scan_data = match record.message {
+            Message::FusedScanData(ref scan_data) => scan_data,
+            ref message => panic!("decoded as {:?}", message),
+        };
+        assert_eq!(scan_data.scanner_info_vec[0].device_id, 2);
+
+        let scan = record.message.scan().unwrap();
+        assert_eq!(scan.scan_number, 7);
+        assert_eq!(scan.end_time.secs, 3800000000);
+        assert_eq!(scan.points.len(), 1);
+        let point = &scan.points[0];
+        assert_eq!((point.x, point.y, point.z), (3.0, 4.0, 0.5));
+        assert!((point.distance - 5.0249).abs() < 1e-3);
+        assert_eq!((point.layer, point.echo, point.flags), (1, 0, 0x1234));
+        assert_eq!(point.echo_pulse_width, 25);
+        assert_eq!(point.device_id, Some(2));
+        assert!((point.time - (1591011200.0 + 0.001)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn truncated_float_scan() {
+        let payload = [0; 30];
+        let mut buffer = payload.to_vec();
+        // one scanner info that the payload is too short for
+        buffer[20] = 1;
+        let record = fixtures::record(0x2209, &buffer);
+        assert!(matches!(record.message, Message::Payload(_)));
+    }
+
+    #[test]
+    fn truncated_scan() {
+        let mut buffer = vec![0; 44];
+        // one scan point that the payload is too short for
+        buffer[28] = 1;
+        let record = fixtures::record(0x2202, &buffer);
+        assert!(matches!(record.message, Message::Payload(_)));
+    }
+
+    #[test]
+    fn truncated_objects() {
+        let mut buffer = vec![0; 10 + 58];
+        // the second object is missing
+        buffer[8] = 2;
+        let record = fixtures::record(0x2221, &buffer);
+        assert!(matches!(record.message, Message::Payload(_)));
+        // contour points past the end of the payload
+        buffer[8] = 1;
+        buffer[10 + 56] = 1;
+        let record = fixtures::record(0x2221, &buffer);
+        assert!(matches!(record.message, Message::Payload(_)));
+    }
+
+    #[test]
+    fn short_payloads() {
+        for &(data_type, size) in [(0x2805, 38), (0x2030, 8), (0x7100, 30)].iter() {
+            let buffer = vec![0; size - 1];
+            let record = fixtures::record(data_type, &buffer);
+            assert!(matches!(record.message, Message::Payload(_)), "{:#x}", data_type);
+            let buffer = vec![0; size];
+            let record = fixtures::record(data_type, &buffer);
+            assert!(!matches!(record.message, Message::Payload(_)), "{:#x}", data_type);
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde_yaml;
+use sha2::{Digest, Sha256};
+
+use compression;
+use config::Sensor;
+use output::RecordWriter;
+use select::SplitWriter;
+use stream::{Message, Record};
+use {DataType, NtpTime, SensorInfo};
+
+pub const GAP_SECONDS: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MountingPose {
+    x: f64,
+    y: f64,
+    z: f64,
+    yaw: f64,
+    pitch: f64,
+    roll: f64,
+}
+
+impl MountingPose {
+    // "x,y,z,yaw,pitch,roll" in meters and degrees
+    pub fn parse(text: &str) -> Option<MountingPose> {
+        let values = text.split(',').map(|value| value.trim().parse::<f64>().ok()).collect::<Option<Vec<f64>>>()?;
+        if values.len() != 6 {
+            return None;
+        }
+        Some(MountingPose {
+            x: values[0],
+            y: values[1],
+            z: values[2],
+            yaw: values[3],
+            pitch: values[4],
+            roll: values[5],
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Firmware {
+    firmware_version: String,
+    fpga_version: String,
+    serial_number: String,
+    fpga_version_date: [u16; 3],
+    dsp_version_date: [u16; 3],
+}
+
+impl Firmware {
+    fn new(sensor_info: &SensorInfo) -> Firmware {
+        Firmware {
+            firmware_version: format!("{:04x}", sensor_info.firmware_version),
+            fpga_version: format!("{:04x}", sensor_info.fpga_version),
+            serial_number: format!("{:04x}-{:04x}", sensor_info.serial_number_0, sensor_info.serial_number_1),
+            fpga_version_date: sensor_info.fpga_version_date,
+            dsp_version_date: sensor_info.dsp_version_date,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Timestamp {
+    ntp: NtpTime,
+    utc: String,
+}
+
+impl Timestamp {
+    fn new(ntp: &NtpTime) -> Timestamp {
+        Timestamp {
+            ntp: ntp.clone(),
+            utc: ntp.datetime().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Gap {
+    start: Timestamp,
+    end: Timestamp,
+    seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct FileEntry {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+impl FileEntry {
+    fn new(path: &Path) -> io::Result<FileEntry> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let len = file.read(&mut buffer)?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buffer[..len]);
+        }
+        let sha256 = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(FileEntry {
+            path: path.file_name().unwrap().to_string_lossy().into_owned(),
+            size: fs::metadata(path)?.len(),
+            sha256,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SensorEntry {
+    name: String,
+    address: String,
+    device_id: Option<u8>,
+    firmware: Option<Firmware>,
+    mounting_pose: Option<MountingPose>,
+    message_counts: BTreeMap<String, u64>,
+    gaps: Vec<Gap>,
+    #[serde(skip)]
+    last_time: Option<(f64, NtpTime)>,
+}
+
+impl SensorEntry {
+    fn new(name: &str, address: &str, mounting_pose: Option<MountingPose>) -> SensorEntry {
+        SensorEntry {
+            name: name.to_string(),
+            address: address.to_string(),
+            device_id: None,
+            firmware: None,
+            mounting_pose,
+            message_counts: BTreeMap::new(),
+            gaps: Vec::new(),
+            last_time: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Manifest {
+    sensors: Vec<SensorEntry>,
+    start_time: Option<Timestamp>,
+    end_time: Option<Timestamp>,
+    files: Vec<FileEntry>,
+}
+
+// "drive.ndjson.zst" gets "drive.manifest.yaml" next to it, written once the
+// recording is closed and every file can be checksummed
+pub struct ManifestWriter {
+    path: PathBuf,
+    writer: SplitWriter,
+    manifest: Manifest,
+    last_time: Option<(f64, NtpTime)>,
+    // sensors whose devices are listed instead of them, when split by device
+    ecus: Vec<Sensor>,
+}
+
+impl ManifestWriter {
+    pub fn new(writer: SplitWriter, sensors: &[Sensor], split_devices: bool) -> ManifestWriter {
+        let (entries, ecus) = if split_devices {
+            (Vec::new(), sensors.to_vec())
+        } else {
+            let entries = sensors
+                .iter()
+                .map(|sensor| SensorEntry::new(&sensor.name, &sensor.address, sensor.mounting_pose))
+                .collect();
+            (entries, Vec::new())
+        };
+        ManifestWriter {
+            path: compression::strip_extension(writer.path()).with_extension("manifest.yaml"),
+            writer,
+            manifest: Manifest {
+                sensors: entries,
+                ..Default::default()
+            },
+            last_time: None,
+            ecus,
+        }
+    }
+
+    // records without a source are from the first sensor, a device gets an
+    // entry of its own when it is first seen
+    fn sensor(&mut self, record: &Record) -> &mut SensorEntry {
+        let source = record.source.as_deref().unwrap_or("");
+        let index = match self.manifest.sensors.iter().position(|sensor| sensor.name == source) {
+            Some(index) => index,
+            None if self.ecus.is_empty() => 0,
+            None => {
+                // "front/device2" is behind "front", "device2" behind the only sensor
+                let ecu = self
+                    .ecus
+                    .iter()
+                    .find(|ecu| source.starts_with(&format!("{}/", ecu.name)))
+                    .unwrap_or(&self.ecus[0]);
+                self.manifest.sensors.push(SensorEntry::new(source, &ecu.address, None));
+                self.manifest.sensors.len() - 1
+            },
+        };
+        &mut self.manifest.sensors[index]
+    }
+
+    // takes the firmware from sensor info, also when it is not recorded
+    pub fn observe(&mut self, record: &Record) {
+        if let Message::SensorInfo(ref sensor_info) = record.message {
+            self.sensor(record).firmware = Some(Firmware::new(sensor_info));
+        }
+    }
+
+    pub fn accepts(&self, data_type: &DataType) -> bool {
+        self.writer.accepts(data_type)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.writer.bytes()
+    }
+
+    pub fn print_summary(&self) {
+        if let (Some(start), Some((end, last))) = (&self.manifest.start_time, &self.last_time) {
+            let seconds = end - start.ntp.unix_timestamp();
+            println!("recorded from {} to {} ({:.1} s)", start.utc, last.datetime().to_rfc3339(), seconds);
+        }
+        let several = self.manifest.sensors.len() > 1 || !self.ecus.is_empty();
+        let indent = if several { "    " } else { "  " };
+        for sensor in &self.manifest.sensors {
+            if several {
+                println!("  {} ({}):", sensor.name, sensor.address);
+            }
+            for (data_type, count) in &sensor.message_counts {
+                println!("{}{}: {}", indent, data_type, count);
+            }
+            println!("{}gaps: {}", indent, sensor.gaps.len());
+        }
+        for file in self.writer.files() {
+            println!("  {}", file.display());
+        }
+        println!("  manifest: {}", self.path.display());
+    }
+
+    fn save(&mut self, files: &[PathBuf]) -> io::Result<()> {
+        for file in files {
+            let name = file.file_name().unwrap().to_string_lossy();
+            if !self.manifest.files.iter().any(|entry| entry.path == name) {
+                self.manifest.files.push(FileEntry::new(file)?);
+            }
+        }
+        self.manifest.end_time = self.last_time.as_ref().map(|(_, last)| Timestamp::new(last));
+        let document = serde_yaml::to_string(&self.manifest)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.path, document)
+    }
+}
+
+impl RecordWriter for ManifestWriter {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if !self.writer.accepts(&record.data_type) {
+            return Ok(());
+        }
+        self.writer.write(record)?;
+
+        let ntp_time = &record.header.ntp_time;
+        if self.manifest.start_time.is_none() {
+            self.manifest.start_time = Some(Timestamp::new(ntp_time));
+        }
+        self.last_time = Some((record.timestamp, ntp_time.clone()));
+        // gaps are per sensor, their messages are interleaved
+        let sensor = self.sensor(record);
+        if sensor.device_id.is_none() {
+            sensor.device_id = Some(record.header.device_id);
+        }
+        if let Some((timestamp, ref last)) = sensor.last_time {
+            if record.timestamp - timestamp > GAP_SECONDS {
+                sensor.gaps.push(Gap {
+                    start: Timestamp::new(last),
+                    end: Timestamp::new(ntp_time),
+                    seconds: record.timestamp - timestamp,
+                });
+            }
+        }
+        sensor.last_time = Some((record.timestamp, ntp_time.clone()));
+        *sensor.message_counts.entry(format!("{:?}", record.data_type)).or_insert(0) += 1;
+
+        // rotated files are final, so the manifest is kept up to date with them
+        let closed = self.writer.closed_files();
+        if closed.len() > self.manifest.files.len() {
+            self.save(&closed)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, fsync: bool) -> io::Result<()> {
+        self.writer.flush(fsync)
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.writer.close()?;
+        let files = self.writer.files();
+        self.save(&files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_yaml::{self, Value};
+    use sha2::{Digest, Sha256};
+
+    use super::{ManifestWriter, MountingPose};
+    use config::Sensor;
+    use fixtures::{object_data, record_at, scan_data, temp_path};
+    use output::RecordWriter;
+    use rotate::Rotation;
+    use select::{self, SplitWriter};
+
+    #[test]
+    fn mounting_poses() {
+        let pose = MountingPose::parse("1.2, 0,1.5,180,-1,0.5").unwrap();
+        assert_eq!((pose.x, pose.z, pose.yaw, pose.pitch, pose.roll), (1.2, 1.5, 180.0, -1.0, 0.5));
+        assert!(MountingPose::parse("1,2,3,4,5").is_none());
+        assert!(MountingPose::parse("1,2,3,4,5,6,7").is_none());
+        assert!(MountingPose::parse("1,2,3,4,5,x").is_none());
+    }
+
+    #[test]
+    fn manifest() {
+        let dir = temp_path("manifest");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("drive.ndjson");
+        let sensor = Sensor {
+            name: "front".to_string(),
+            address: "192.168.0.1:12002".to_string(),
+            mounting_pose: MountingPose::parse("1.2,0,1.5,0,0,0"),
+        };
+        let writer = SplitWriter::new(&path, &select::parse("scan,object").unwrap(), Rotation::default());
+        let mut writer = Box::new(ManifestWriter::new(writer, &[sensor], false));
+        // a gap before the last scan, the error data is not recorded
+        for &seconds in [0.0, 0.1, 0.2, 1.0].iter() {
+            writer.write(&record_at(0x2202, &scan_data(seconds), seconds)).unwrap();
+        }
+        writer.write(&record_at(0x2221, &object_data(&[1]), 1.0)).unwrap();
+        writer.write(&record_at(0x2030, &[0; 8], 1.0)).unwrap();
+        writer.finish().unwrap();
+        let manifest: Value = serde_yaml::from_str(&fs::read_to_string(dir.join("drive.manifest.yaml")).unwrap()).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let sensor = &manifest["sensors"][0];
+        assert_eq!(sensor["name"], "front");
+        assert_eq!(sensor["device_id"], 1);
+        assert_eq!(sensor["mounting_pose"]["z"], 1.5);
+        assert_eq!(sensor["message_counts"]["ScanData"], 4);
+        assert_eq!(sensor["message_counts"]["ObjectData"], 1);
+        assert!(sensor["message_counts"]["Error"].is_null());
+        assert_eq!(sensor["gaps"].as_sequence().unwrap().len(), 1);
+        assert_eq!(manifest["start_time"]["utc"], "2020-06-01T11:33:20+00:00");
+        assert_eq!(manifest["end_time"]["ntp"]["secs"].as_u64(), Some(3800000001));
+
+        let files = manifest["files"].as_sequence().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0]["path"], "drive.ndjson");
+        assert_eq!(files[0]["size"].as_u64(), Some(data.len() as u64));
+        let sha256: String = Sha256::digest(&data).iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(files[0]["sha256"], Value::String(sha256));
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use compression::OutputFile;
+use foxglove;
+use output::RecordWriter;
+use stream::Record;
+
+const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+const CHUNK_SIZE: usize = 1024 * 1024;
+// smallest chunk closed by a periodic flush
+const MIN_CHUNK_SIZE: usize = 256 * 1024;
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_MESSAGE_INDEX: u8 = 0x07;
+const OP_CHUNK_INDEX: u8 = 0x08;
+const OP_STATISTICS: u8 = 0x0b;
+const OP_SUMMARY_OFFSET: u8 = 0x0e;
+const OP_DATA_END: u8 = 0x0f;
+
+fn put_string(buffer: &mut Vec<u8>, text: &str) {
+    put_bytes(buffer, text.as_bytes());
+}
+
+fn put_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.write_u32::<LittleEndian>(bytes.len() as u32).unwrap();
+    buffer.extend_from_slice(bytes);
+}
+
+fn put_record(buffer: &mut Vec<u8>, opcode: u8, content: &[u8]) {
+    buffer.write_u8(opcode).unwrap();
+    buffer.write_u64::<LittleEndian>(content.len() as u64).unwrap();
+    buffer.extend_from_slice(content);
+}
+
+// messages are json encoded foxglove schemas, grouped in uncompressed chunks
+pub struct McapWriter<W: Write> {
+    writer: W,
+    position: u64,
+    schemas: HashMap<&'static str, u16>,
+    channels: HashMap<String, u16>,
+    summary_schemas: Vec<u8>,
+    summary_channels: Vec<u8>,
+    summary_chunk_indexes: Vec<u8>,
+    chunk: Vec<u8>,
+    chunk_start_time: u64,
+    chunk_end_time: u64,
+    message_indexes: BTreeMap<u16, Vec<(u64, u64)>>,
+    chunk_count: u32,
+    message_count: u64,
+    message_start_time: u64,
+    message_end_time: u64,
+    channel_message_counts: BTreeMap<u16, u64>,
+    sequence: u32,
+}
+
+impl<W: Write> McapWriter<W> {
+    pub fn new(writer: W) -> io::Result<McapWriter<W>> {
+        let mut mcap_writer = McapWriter {
+            writer,
+            position: 0,
+            schemas: HashMap::new(),
+            channels: HashMap::new(),
+            summary_schemas: Vec::new(),
+            summary_channels: Vec::new(),
+            summary_chunk_indexes: Vec::new(),
+            chunk: Vec::new(),
+            chunk_start_time: u64::MAX,
+            chunk_end_time: 0,
+            message_indexes: BTreeMap::new(),
+            chunk_count: 0,
+            message_count: 0,
+            message_start_time: u64::MAX,
+            message_end_time: 0,
+            channel_message_counts: BTreeMap::new(),
+            sequence: 0,
+        };
+        mcap_writer.write_all(MAGIC)?;
+        let mut content = Vec::new();
+        put_string(&mut content, "");
+        put_string(&mut content, "lidar");
+        let mut record = Vec::new();
+        put_record(&mut record, OP_HEADER, &content);
+        mcap_writer.write_all(&record)?;
+        Ok(mcap_writer)
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn channel_id(&mut self, channel: &foxglove::Channel) -> io::Result<u16> {
+        if let Some(id) = self.channels.get(&channel.topic) {
+            return Ok(*id);
+        }
+        let schema_id = match self.schemas.get(channel.schema_name) {
+            Some(id) => *id,
+            None => {
+                let id = (self.schemas.len() + 1) as u16;
+                let mut content = Vec::new();
+                content.write_u16::<LittleEndian>(id)?;
+                put_string(&mut content, channel.schema_name);
+                put_string(&mut content, "jsonschema");
+                put_bytes(&mut content, channel.schema.as_bytes());
+                let mut record = Vec::new();
+                put_record(&mut record, OP_SCHEMA, &content);
+                self.write_all(&record)?;
+                self.summary_schemas.extend_from_slice(&record);
+                self.schemas.insert(channel.schema_name, id);
+                id
+            },
+        };
+        let id = self.channels.len() as u16;
+        let mut content = Vec::new();
+        content.write_u16::<LittleEndian>(id)?;
+        content.write_u16::<LittleEndian>(schema_id)?;
+        put_string(&mut content, &channel.topic);
+        put_string(&mut content, "json");
+        // empty metadata map
+        content.write_u32::<LittleEndian>(0)?;
+        let mut record = Vec::new();
+        put_record(&mut record, OP_CHANNEL, &content);
+        self.write_all(&record)?;
+        self.summary_channels.extend_from_slice(&record);
+        self.channels.insert(channel.topic.clone(), id);
+        Ok(id)
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk_start_offset = self.position;
+        let mut content = Vec::new();
+        content.write_u64::<LittleEndian>(self.chunk_start_time)?;
+        content.write_u64::<LittleEndian>(self.chunk_end_time)?;
+        content.write_u64::<LittleEndian>(self.chunk.len() as u64)?;
+        // crc of zero is not validated by readers
+        content.write_u32::<LittleEndian>(0)?;
+        put_string(&mut content, "");
+        content.write_u64::<LittleEndian>(self.chunk.len() as u64)?;
+        content.extend_from_slice(&self.chunk);
+        let mut record = Vec::new();
+        put_record(&mut record, OP_CHUNK, &content);
+        self.write_all(&record)?;
+        let chunk_length = self.position - chunk_start_offset;
+
+        let message_index_start = self.position;
+        let mut message_index_offsets = Vec::new();
+        let message_indexes = ::std::mem::take(&mut self.message_indexes);
+        for (channel_id, entries) in message_indexes {
+            message_index_offsets.write_u16::<LittleEndian>(channel_id)?;
+            message_index_offsets.write_u64::<LittleEndian>(self.position)?;
+            let mut content = Vec::new();
+            content.write_u16::<LittleEndian>(channel_id)?;
+            content.write_u32::<LittleEndian>((entries.len() * 16) as u32)?;
+            for (log_time, offset) in entries {
+                content.write_u64::<LittleEndian>(log_time)?;
+                content.write_u64::<LittleEndian>(offset)?;
+            }
+            let mut record = Vec::new();
+            put_record(&mut record, OP_MESSAGE_INDEX, &content);
+            self.write_all(&record)?;
+        }
+        let message_index_length = self.position - message_index_start;
+
+        let mut content = Vec::new();
+        content.write_u64::<LittleEndian>(self.chunk_start_time)?;
+        content.write_u64::<LittleEndian>(self.chunk_end_time)?;
+        content.write_u64::<LittleEndian>(chunk_start_offset)?;
+        content.write_u64::<LittleEndian>(chunk_length)?;
+        put_bytes(&mut content, &message_index_offsets);
+        content.write_u64::<LittleEndian>(message_index_length)?;
+        put_string(&mut content, "");
+        content.write_u64::<LittleEndian>(self.chunk.len() as u64)?;
+        content.write_u64::<LittleEndian>(self.chunk.len() as u64)?;
+        put_record(&mut self.summary_chunk_indexes, OP_CHUNK_INDEX, &content);
+
+        self.chunk.clear();
+        self.chunk_start_time = u64::MAX;
+        self.chunk_end_time = 0;
+        self.chunk_count += 1;
+        Ok(())
+    }
+
+    fn write_summary(&mut self) -> io::Result<()> {
+        let mut statistics = Vec::new();
+        statistics.write_u64::<LittleEndian>(self.message_count)?;
+        statistics.write_u16::<LittleEndian>(self.schemas.len() as u16)?;
+        statistics.write_u32::<LittleEndian>(self.channels.len() as u32)?;
+        statistics.write_u32::<LittleEndian>(0)?;
+        statistics.write_u32::<LittleEndian>(0)?;
+        statistics.write_u32::<LittleEndian>(self.chunk_count)?;
+        if self.message_count == 0 {
+            self.message_start_time = 0;
+        }
+        statistics.write_u64::<LittleEndian>(self.message_start_time)?;
+        statistics.write_u64::<LittleEndian>(self.message_end_time)?;
+        let mut channel_message_counts = Vec::new();
+        for (channel_id, count) in &self.channel_message_counts {
+            channel_message_counts.write_u16::<LittleEndian>(*channel_id)?;
+            channel_message_counts.write_u64::<LittleEndian>(*count)?;
+        }
+        put_bytes(&mut statistics, &channel_message_counts);
+        let mut summary_statistics = Vec::new();
+        put_record(&mut summary_statistics, OP_STATISTICS, &statistics);
+
+        let summary_start = self.position;
+        let groups = vec![
+            (OP_SCHEMA, ::std::mem::take(&mut self.summary_schemas)),
+            (OP_CHANNEL, ::std::mem::take(&mut self.summary_channels)),
+            (OP_STATISTICS, summary_statistics),
+            (OP_CHUNK_INDEX, ::std::mem::take(&mut self.summary_chunk_indexes)),
+        ];
+        let mut summary_offsets = Vec::new();
+        for (opcode, records) in groups {
+            if records.is_empty() {
+                continue;
+            }
+            let mut content = Vec::new();
+            content.write_u8(opcode)?;
+            content.write_u64::<LittleEndian>(self.position)?;
+            content.write_u64::<LittleEndian>(records.len() as u64)?;
+            put_record(&mut summary_offsets, OP_SUMMARY_OFFSET, &content);
+            self.write_all(&records)?;
+        }
+        let summary_offset_start = self.position;
+        self.write_all(&summary_offsets)?;
+
+        let mut content = Vec::new();
+        content.write_u64::<LittleEndian>(summary_start)?;
+        content.write_u64::<LittleEndian>(summary_offset_start)?;
+        content.write_u32::<LittleEndian>(0)?;
+        let mut record = Vec::new();
+        put_record(&mut record, OP_FOOTER, &content);
+        self.write_all(&record)
+    }
+}
+
+impl RecordWriter for McapWriter<OutputFile> {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let channel_id = self.channel_id(&foxglove::record_channel(record))?;
+        let log_time = record.header.ntp_time.unix_nanos();
+
+        let offset = self.chunk.len() as u64;
+        let mut content = Vec::new();
+        content.write_u16::<LittleEndian>(channel_id)?;
+        content.write_u32::<LittleEndian>(self.sequence)?;
+        content.write_u64::<LittleEndian>(log_time)?;
+        content.write_u64::<LittleEndian>(log_time)?;
+        content.extend_from_slice(&foxglove::encode(record));
+        put_record(&mut self.chunk, OP_MESSAGE, &content);
+
+        self.message_indexes.entry(channel_id).or_default().push((log_time, offset));
+        *self.channel_message_counts.entry(channel_id).or_insert(0) += 1;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.message_count += 1;
+        self.chunk_start_time = self.chunk_start_time.min(log_time);
+        self.chunk_end_time = self.chunk_end_time.max(log_time);
+        self.message_start_time = self.message_start_time.min(log_time);
+        self.message_end_time = self.message_end_time.max(log_time);
+
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, fsync: bool) -> io::Result<()> {
+        if self.chunk.len() >= MIN_CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+        self.writer.sync(fsync)
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.flush_chunk()?;
+        let mut record = Vec::new();
+        put_record(&mut record, OP_DATA_END, &[0; 4]);
+        self.write_all(&record)?;
+        self.write_summary()?;
+        self.write_all(MAGIC)?;
+        self.writer.finish()
+    }
+}
+
+// truncates a file that was not closed after its last complete record in the
+// data section, and closes it again without a summary
+pub fn repair(data: &mut Vec<u8>) -> io::Result<bool> {
+    if !data.starts_with(MAGIC) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an mcap file"));
+    }
+    let footer = data.len().saturating_sub(MAGIC.len() + 29);
+    if data.len() >= 2 * MAGIC.len() + 29 && data.ends_with(MAGIC) && data[footer] == OP_FOOTER {
+        return Ok(false);
+    }
+    let mut position = MAGIC.len();
+    while position + 9 <= data.len() && data[position] != OP_DATA_END {
+        let len = LittleEndian::read_u64(&data[position + 1..position + 9]);
+        if len > (data.len() - position - 9) as u64 {
+            break;
+        }
+        position += 9 + len as usize;
+    }
+    data.truncate(position);
+    put_record(data, OP_DATA_END, &[0; 4]);
+    put_record(data, OP_FOOTER, &[0; 20]);
+    data.extend_from_slice(MAGIC);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+
+    use byteorder::{ByteOrder, LittleEndian};
+    use serde_json::{self, Value};
+
+    use super::{McapWriter, MAGIC, OP_CHANNEL, OP_CHUNK, OP_FOOTER, OP_MESSAGE, OP_SCHEMA, OP_STATISTICS};
+    use compression::OutputFile;
+    use fixtures::{self, scan_data, temp_path};
+    use output::RecordWriter;
+    use stream::Record;
+
+    fn record(data_type: u16, payload: &[u8], source: Option<&str>) -> Record {
+        let mut record = fixtures::record(data_type, payload);
+        record.source = source.map(str::to_string);
+        record
+    }
+
+    fn string(data: &[u8], position: &mut usize) -> String {
+        let len = LittleEndian::read_u32(&data[*position..*position + 4]) as usize;
+        let text = String::from_utf8(data[*position + 4..*position + 4 + len].to_vec()).unwrap();
+        *position += 4 + len;
+        text
+    }
+
+    // opcode and content of every record in `data`
+    fn records(data: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut records = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let len = LittleEndian::read_u64(&data[position + 1..position + 9]) as usize;
+            records.push((data[position], &data[position + 9..position + 9 + len]));
+            position += 9 + len;
+        }
+        records
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut movement = [0; 38];
+        LittleEndian::write_i32(&mut movement[20..24], 20000);
+        let written = vec![
+            record(0x2202, &scan_data(0.0), Some("front")),
+            record(0x2202, &scan_data(0.0), Some("rear")),
+            record(0x2805, &movement, None),
+            record(0x2030, &[1, 0, 0, 0, 0, 0, 0, 0], None),
+        ];
+        let path = temp_path("round_trip.mcap");
+        let mut writer = Box::new(McapWriter::new(OutputFile::create(&path).unwrap()).unwrap());
+        for record in &written {
+            writer.write(record).unwrap();
+        }
+        // too small to close the chunk
+        writer.flush(false).unwrap();
+        writer.finish().unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(data.starts_with(MAGIC) && data.ends_with(MAGIC));
+        let footer = data.len() - MAGIC.len() - 29;
+        assert_eq!(data[footer], OP_FOOTER);
+        let summary_start = LittleEndian::read_u64(&data[footer + 9..footer + 17]) as usize;
+
+        let mut schemas = HashMap::new();
+        let mut channels = HashMap::new();
+        let mut messages = Vec::new();
+        let mut chunks = 0;
+        for (opcode, content) in records(&data[MAGIC.len()..summary_start]) {
+            match opcode {
+                OP_SCHEMA => {
+                    let mut position = 2;
+                    schemas.insert(LittleEndian::read_u16(content), string(content, &mut position));
+                },
+                OP_CHANNEL => {
+                    let mut position = 4;
+                    let schema = schemas[&LittleEndian::read_u16(&content[2..4])].clone();
+                    channels.insert(LittleEndian::read_u16(content), (string(content, &mut position), schema));
+                },
+                OP_CHUNK => {
+                    chunks += 1;
+                    let mut position = 28;
+                    assert_eq!(string(content, &mut position), "");
+                    for (opcode, message) in records(&content[position + 8..]) {
+                        assert_eq!(opcode, OP_MESSAGE);
+                        let value: Value = serde_json::from_slice(&message[22..]).unwrap();
+                        messages.push((channels[&LittleEndian::read_u16(message)].clone(), value));
+                    }
+                },
+                _ => {},
+            }
+        }
+        assert_eq!(chunks, 1);
+        let topics: Vec<(&str, &str)> =
+            messages.iter().map(|((topic, schema), _)| (topic.as_str(), schema.as_str())).collect();
+        assert_eq!(
+            topics,
+            vec![
+                ("/lux/front/scan", "foxglove.PointCloud"),
+                ("/lux/rear/scan", "foxglove.PointCloud"),
+                ("/lux/movement", "foxglove.PoseInFrame"),
+                ("/lux/error", "lux.Error"),
+            ]
+        );
+        assert_eq!(messages[2].1["pose"]["position"]["x"], 2.0);
+        assert_eq!(messages[3].1["error_register_1"], 1);
+
+        let statistics = records(&data[summary_start..footer])
+            .into_iter()
+            .find(|&(opcode, _)| opcode == OP_STATISTICS)
+            .unwrap()
+            .1;
+        assert_eq!(LittleEndian::read_u64(&statistics[0..8]), 4);
+        assert_eq!(LittleEndian::read_u16(&statistics[8..10]), 3);
+        assert_eq!(LittleEndian::read_u32(&statistics[10..14]), 4);
+        assert_eq!(LittleEndian::read_u32(&statistics[22..26]), 1);
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use compression::{self, Compression, OutputFile};
+use mcap::McapWriter;
+use rosbag::BagWriter;
+use stream::{Format, Record, StreamWriter};
+
+pub trait RecordWriter {
+    fn write(&mut self, record: &Record) -> io::Result<()>;
+    // makes what was written so far readable from the file, and with
+    // `fsync` durable on disk. chunked formats keep a small last chunk open
+    // until it has grown enough.
+    fn flush(&mut self, fsync: bool) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+// the format of a recording by its extension, used for reading and writing.
+// anything else is read as raw idc frames, which cannot be written.
+pub fn format_from_path(path: &Path) -> Option<&'static str> {
+    let path = compression::strip_extension(path);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("mcap") => Some("mcap"),
+        Some("bag") => Some("bag"),
+        _ => match Format::from_path(&path)? {
+            Format::Yaml => Some("yaml"),
+            Format::Ndjson => Some("ndjson"),
+        },
+    }
+}
+
+pub fn create(path: &Path, format: &str) -> io::Result<Box<dyn RecordWriter>> {
+    match format {
+        "mcap" => Ok(Box::new(McapWriter::new(OutputFile::create(path)?)?)),
+        // the bag header is rewritten in place when the bag is closed
+        "bag" => match Compression::from_path(path) {
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "bag files cannot be compressed")),
+            None => Ok(Box::new(BagWriter::new(BufWriter::new(File::create(path)?))?)),
+        },
+        _ => match Format::from_name(format) {
+            Some(format) => Ok(Box::new(StreamWriter::new(OutputFile::create(path)?, format))),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown output format: {}", format))),
+        },
+    }
+}
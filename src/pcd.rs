@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use Scan;
+
+pub fn write_scan(dir: &Path, scan: &Scan, binary: bool) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(dir.join(scan.file_name("pcd")))?);
+    let points = scan.points.len();
+
+    writeln!(file, "# .PCD v0.7 - Point Cloud Data file format")?;
+    writeln!(file, "VERSION 0.7")?;
+    writeln!(file, "FIELDS x y z layer echo flags echo_pulse_width timestamp")?;
+    writeln!(file, "SIZE 4 4 4 1 1 2 2 8")?;
+    writeln!(file, "TYPE F F F U U U U F")?;
+    writeln!(file, "COUNT 1 1 1 1 1 1 1 1")?;
+    writeln!(file, "WIDTH {}", points)?;
+    writeln!(file, "HEIGHT 1")?;
+    writeln!(file, "VIEWPOINT 0 0 0 1 0 0 0")?;
+    writeln!(file, "POINTS {}", points)?;
+    writeln!(file, "DATA {}", if binary { "binary" } else { "ascii" })?;
+
+    for point in &scan.points {
+        if binary {
+            file.write_f32::<LittleEndian>(point.x)?;
+            file.write_f32::<LittleEndian>(point.y)?;
+            file.write_f32::<LittleEndian>(point.z)?;
+            file.write_u8(point.layer)?;
+            file.write_u8(point.echo)?;
+            file.write_u16::<LittleEndian>(point.flags)?;
+            file.write_u16::<LittleEndian>(point.echo_pulse_width)?;
+            file.write_f64::<LittleEndian>(point.time)?;
+        } else {
+            writeln!(
+                file,
+                "{} {} {} {} {} {} {} {:.6}",
+                point.x,
+                point.y,
+                point.z,
+                point.layer,
+                point.echo,
+                point.flags,
+                point.echo_pulse_width,
+                point.time
+            )?;
+        }
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::write_scan;
+    use fixtures::{point, scan, temp_path};
+    use Point;
+
+    // header lines and the data after them
+    fn read(binary: bool) -> (Vec<String>, Vec<u8>) {
+        let dir = temp_path(if binary { "pcd-binary" } else { "pcd-ascii" });
+        fs::create_dir_all(&dir).unwrap();
+        let scan = scan(vec![
+            point(1.0, 2.0, 3.0),
+            Point {
+                layer: 3,
+                echo: 1,
+                flags: 0x0102,
+                echo_pulse_width: 250,
+                time: 1591011200.5,
+                ..point(-1.5, 0.0, 0.25)
+            },
+        ]);
+        write_scan(&dir, &scan, binary).unwrap();
+        let data = fs::read(dir.join(scan.file_name("pcd"))).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let mut lines = Vec::new();
+        let mut position = 0;
+        while !lines.last().is_some_and(|line: &String| line.starts_with("DATA")) {
+            let end = position + data[position..].iter().position(|&byte| byte == b'\n').unwrap();
+            lines.push(String::from_utf8(data[position..end].to_vec()).unwrap());
+            position = end + 1;
+        }
+        (lines, data[position..].to_vec())
+    }
+
+    fn field<'a>(lines: &'a [String], name: &str) -> Vec<&'a str> {
+        let line = lines.iter().find(|line| line.starts_with(&format!("{} ", name))).unwrap();
+        line.split(' ').skip(1).collect()
+    }
+
+    #[test]
+    fn header() {
+        let (lines, _) = read(false);
+        let fields = field(&lines, "FIELDS");
+        assert_eq!(fields.len(), field(&lines, "SIZE").len());
+        assert_eq!(fields.len(), field(&lines, "TYPE").len());
+        assert_eq!(fields.len(), field(&lines, "COUNT").len());
+        assert_eq!(field(&lines, "WIDTH"), vec!["2"]);
+        assert_eq!(field(&lines, "POINTS"), vec!["2"]);
+        assert_eq!(field(&lines, "DATA"), vec!["ascii"]);
+    }
+
+    #[test]
+    fn ascii_rows() {
+        let (lines, data) = read(false);
+        let rows: Vec<String> = String::from_utf8(data).unwrap().lines().map(str::to_string).collect();
+        assert_eq!(rows.len(), 2);
+        for row in &rows {
+            assert_eq!(row.split(' ').count(), field(&lines, "FIELDS").len());
+        }
+        assert_eq!(rows[1], "-1.5 0 0.25 3 1 258 250 1591011200.500000");
+    }
+
+    #[test]
+    fn binary_layout() {
+        let (lines, data) = read(true);
+        assert_eq!(field(&lines, "DATA"), vec!["binary"]);
+        let sizes: Vec<usize> = field(&lines, "SIZE").iter().map(|size| size.parse().unwrap()).collect();
+        let stride: usize = sizes.iter().sum();
+        assert_eq!(data.len(), 2 * stride);
+        // the offsets follow from the sizes
+        let offset = |index: usize| sizes[..index].iter().sum::<usize>();
+        let point = &data[stride..];
+        assert_eq!(LittleEndian::read_f32(&point[offset(0)..]), -1.5);
+        assert_eq!(point[offset(3)], 3);
+        assert_eq!(point[offset(4)], 1);
+        assert_eq!(LittleEndian::read_u16(&point[offset(5)..]), 0x0102);
+        assert_eq!(LittleEndian::read_u16(&point[offset(6)..]), 250);
+        assert_eq!(LittleEndian::read_f64(&point[offset(7)..]), 1591011200.5);
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use {gps_time, Scan};
+
+pub fn write_scan(dir: &Path, scan: &Scan, binary: bool) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(dir.join(scan.file_name("ply")))?);
+
+    writeln!(file, "ply")?;
+    if binary {
+        writeln!(file, "format binary_little_endian 1.0")?;
+    } else {
+        writeln!(file, "format ascii 1.0")?;
+    }
+    writeln!(file, "element vertex {}", scan.points.len())?;
+    writeln!(file, "property float x")?;
+    writeln!(file, "property float y")?;
+    writeln!(file, "property float z")?;
+    writeln!(file, "property ushort intensity")?;
+    writeln!(file, "property uchar layer")?;
+    writeln!(file, "property uchar echo")?;
+    writeln!(file, "property ushort flags")?;
+    writeln!(file, "property double gps_time")?;
+    writeln!(file, "end_header")?;
+
+    for point in &scan.points {
+        let time = gps_time(point.time);
+        if binary {
+            file.write_f32::<LittleEndian>(point.x)?;
+            file.write_f32::<LittleEndian>(point.y)?;
+            file.write_f32::<LittleEndian>(point.z)?;
+            file.write_u16::<LittleEndian>(point.echo_pulse_width)?;
+            file.write_u8(point.layer)?;
+            file.write_u8(point.echo)?;
+            file.write_u16::<LittleEndian>(point.flags)?;
+            file.write_f64::<LittleEndian>(time)?;
+        } else {
+            writeln!(
+                file,
+                "{} {} {} {} {} {} {} {:.6}",
+                point.x,
+                point.y,
+                point.z,
+                point.echo_pulse_width,
+                point.layer,
+                point.echo,
+                point.flags,
+                time
+            )?;
+        }
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::write_scan;
+    use fixtures::{point, scan, temp_path};
+    use {gps_time, Point};
+
+    // header lines and the data after them
+    fn read(binary: bool) -> (Vec<String>, Vec<u8>) {
+        let dir = temp_path(if binary { "ply-binary" } else { "ply-ascii" });
+        fs::create_dir_all(&dir).unwrap();
+        let scan = scan(vec![
+            point(1.0, 2.0, 3.0),
+            Point {
+                layer: 3,
+                echo: 1,
+                flags: 0x0102,
+                echo_pulse_width: 250,
+                time: 1591011200.5,
+                ..point(-1.5, 0.0, 0.25)
+            },
+        ]);
+        write_scan(&dir, &scan, binary).unwrap();
+        let data = fs::read(dir.join(scan.file_name("ply"))).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let mut lines = Vec::new();
+        let mut position = 0;
+        while lines.last().map(String::as_str) != Some("end_header") {
+            let end = position + data[position..].iter().position(|&byte| byte == b'\n').unwrap();
+            lines.push(String::from_utf8(data[position..end].to_vec()).unwrap());
+            position = end + 1;
+        }
+        (lines, data[position..].to_vec())
+    }
+
+    // name and size of every vertex property
+    fn properties(lines: &[String]) -> Vec<(String, usize)> {
+        lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("property "))
+            .map(|property| {
+                let mut parts = property.split(' ');
+                let size = match parts.next().unwrap() {
+                    "uchar" => 1,
+                    "ushort" => 2,
+                    "float" => 4,
+                    "double" => 8,
+                    other => panic!("unexpected type {}", other),
+                };
+                (parts.next().unwrap().to_string(), size)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn header() {
+        let (lines, _) = read(false);
+        assert_eq!(lines[0], "ply");
+        assert_eq!(lines[1], "format ascii 1.0");
+        assert_eq!(lines[2], "element vertex 2");
+        let names: Vec<String> = properties(&lines).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["x", "y", "z", "intensity", "layer", "echo", "flags", "gps_time"]);
+    }
+
+    #[test]
+    fn ascii_rows() {
+        let (lines, data) = read(false);
+        let rows: Vec<String> = String::from_utf8(data).unwrap().lines().map(str::to_string).collect();
+        assert_eq!(rows.len(), 2);
+        for row in &rows {
+            assert_eq!(row.split(' ').count(), properties(&lines).len());
+        }
+        assert_eq!(rows[1], format!("-1.5 0 0.25 250 3 1 258 {:.6}", gps_time(1591011200.5)));
+    }
+
+    #[test]
+    fn binary_layout() {
+        let (lines, data) = read(true);
+        assert_eq!(lines[1], "format binary_little_endian 1.0");
+        let sizes: Vec<usize> = properties(&lines).into_iter().map(|(_, size)| size).collect();
+        let stride: usize = sizes.iter().sum();
+        assert_eq!(data.len(), 2 * stride);
+        let offset = |index: usize| sizes[..index].iter().sum::<usize>();
+        let point = &data[stride..];
+        assert_eq!(LittleEndian::read_f32(&point[offset(0)..]), -1.5);
+        assert_eq!(LittleEndian::read_u16(&point[offset(3)..]), 250);
+        assert_eq!(point[offset(4)], 3);
+        assert_eq!(point[offset(5)], 1);
+        assert_eq!(LittleEndian::read_u16(&point[offset(6)..]), 0x0102);
+        assert_eq!(LittleEndian::read_f64(&point[offset(7)..]), gps_time(1591011200.5));
+    }
+}
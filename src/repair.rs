@@ -0,0 +1,45 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use compression::{self, ChunkEncoder};
+use idc;
+use mcap;
+use output;
+use rosbag;
+use stream::{self, Format};
+
+// truncates a recording that was not closed properly at its last complete
+// frame and rewrites whatever trailer its format needs. returns false when
+// there was nothing to repair.
+pub fn repair(path: &Path) -> io::Result<bool> {
+    let mut data = Vec::new();
+    let (compression, complete) = compression::read_recoverable(path, &mut data)?;
+    let format = output::format_from_path(path);
+    let repaired = match (format, format.and_then(Format::from_name)) {
+        (Some("mcap"), _) => mcap::repair(&mut data)?,
+        (Some("bag"), _) => rosbag::repair(&mut data)?,
+        (_, Some(format)) => stream::repair(&mut data, format)?,
+        _ => idc::repair(&mut data)?,
+    };
+    if complete && !repaired {
+        return Ok(false);
+    }
+
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".repair");
+    let mut file = BufWriter::new(File::create(&temporary)?);
+    match compression {
+        Some(compression) => {
+            let mut encoder = ChunkEncoder::new(file, compression);
+            encoder.write_all(&data)?;
+            encoder.finish()?;
+        },
+        None => {
+            file.write_all(&data)?;
+            file.flush()?;
+        },
+    }
+    fs::rename(&temporary, path)?;
+    Ok(true)
+}
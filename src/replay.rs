@@ -0,0 +1,55 @@
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpListener};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use compression;
+use idc::IdcReader;
+
+// serves an idc recording to one client the way a sensor would, paced by the
+// frame timestamps divided by `speed`, or as fast as possible when it is zero
+pub fn replay(path: &Path, address: &str, speed: f64, repeat: bool) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("waiting for a client on {}", address);
+    let (mut stream, client) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    // commands from the client are not answered, but read so that closing
+    // the connection does not reset it
+    let mut commands = stream.try_clone()?;
+    let reader = thread::spawn(move || io::copy(&mut commands, &mut io::sink()));
+    println!("replaying {} to {}", path.display(), client);
+    loop {
+        let mut start: Option<(f64, Instant)> = None;
+        let mut frames = 0;
+        for (header, payload) in IdcReader::new(compression::open(path)?) {
+            let timestamp = header.ntp_time.unix_timestamp();
+            match start {
+                Some((start_time, started)) if speed > 0.0 => {
+                    let due = (timestamp - start_time) / speed - started.elapsed().as_secs_f64();
+                    if due > 0.0 {
+                        thread::sleep(Duration::from_secs_f64(due));
+                    }
+                },
+                Some(_) => {},
+                None => start = Some((timestamp, Instant::now())),
+            }
+            let mut buffer = [0; 24];
+            header.encode(&mut buffer);
+            stream.write_all(&buffer)?;
+            stream.write_all(&payload)?;
+            frames += 1;
+        }
+        if frames == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no idc frames to replay"));
+        }
+        println!("replayed {} frames", frames);
+        if !repeat {
+            break;
+        }
+    }
+    // lets the client read everything before it closes the connection
+    stream.shutdown(Shutdown::Write)?;
+    let _ = reader.join();
+    Ok(())
+}
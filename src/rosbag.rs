@@ -0,0 +1,779 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use output::RecordWriter;
+use stream::{Message, Record};
+use {DataType, MovementData, NtpTime, ObjectData, Scan};
+
+const VERSION: &[u8] = b"#ROSBAG V2.0\n";
+const BAG_HEADER_LENGTH: usize = 4096;
+const CHUNK_SIZE: usize = 768 * 1024;
+// smallest chunk closed by a periodic flush
+const MIN_CHUNK_SIZE: usize = 256 * 1024;
+
+const OP_MESSAGE_DATA: u8 = 0x02;
+const OP_BAG_HEADER: u8 = 0x03;
+const OP_INDEX_DATA: u8 = 0x04;
+const OP_CHUNK: u8 = 0x05;
+const OP_CHUNK_INFO: u8 = 0x06;
+const OP_CONNECTION: u8 = 0x07;
+
+const HEADER_DEFINITION: &str = "\
+================================================================================
+MSG: std_msgs/Header
+uint32 seq
+time stamp
+string frame_id
+";
+
+const VECTOR3_DEFINITION: &str = "\
+================================================================================
+MSG: geometry_msgs/Vector3
+float64 x
+float64 y
+float64 z
+";
+
+const POINT_CLOUD2_DEFINITION: &str = "\
+Header header
+uint32 height
+uint32 width
+PointField[] fields
+bool is_bigendian
+uint32 point_step
+uint32 row_step
+uint8[] data
+bool is_dense
+================================================================================
+MSG: sensor_msgs/PointField
+uint8 INT8=1
+uint8 UINT8=2
+uint8 INT16=3
+uint8 UINT16=4
+uint8 INT32=5
+uint8 UINT32=6
+uint8 FLOAT32=7
+uint8 FLOAT64=8
+string name
+uint32 offset
+uint8 datatype
+uint32 count
+";
+
+const MARKER_ARRAY_DEFINITION: &str = "\
+Marker[] markers
+================================================================================
+MSG: visualization_msgs/Marker
+uint8 ARROW=0
+uint8 CUBE=1
+uint8 SPHERE=2
+uint8 CYLINDER=3
+uint8 LINE_STRIP=4
+uint8 LINE_LIST=5
+uint8 CUBE_LIST=6
+uint8 SPHERE_LIST=7
+uint8 POINTS=8
+uint8 TEXT_VIEW_FACING=9
+uint8 MESH_RESOURCE=10
+uint8 TRIANGLE_LIST=11
+uint8 ADD=0
+uint8 MODIFY=0
+uint8 DELETE=2
+uint8 DELETEALL=3
+Header header
+string ns
+int32 id
+int32 type
+int32 action
+geometry_msgs/Pose pose
+geometry_msgs/Vector3 scale
+std_msgs/ColorRGBA color
+duration lifetime
+bool frame_locked
+geometry_msgs/Point[] points
+std_msgs/ColorRGBA[] colors
+string text
+string mesh_resource
+bool mesh_use_embedded_materials
+================================================================================
+MSG: geometry_msgs/Pose
+Point position
+Quaternion orientation
+================================================================================
+MSG: geometry_msgs/Point
+float64 x
+float64 y
+float64 z
+================================================================================
+MSG: geometry_msgs/Quaternion
+float64 x
+float64 y
+float64 z
+float64 w
+================================================================================
+MSG: std_msgs/ColorRGBA
+float32 r
+float32 g
+float32 b
+float32 a
+";
+
+const TWIST_STAMPED_DEFINITION: &str = "\
+Header header
+Twist twist
+================================================================================
+MSG: geometry_msgs/Twist
+Vector3 linear
+Vector3 angular
+";
+
+// marker types
+const ARROW: i32 = 0;
+const CUBE: i32 = 1;
+const TEXT_VIEW_FACING: i32 = 9;
+
+// point field types
+const UINT8: u8 = 2;
+const FLOAT32: u8 = 7;
+
+// ros time as (sec, nsec)
+type Time = (u32, u32);
+
+struct Connection {
+    topic: String,
+    message_type: &'static str,
+    md5sum: &'static str,
+    definition: String,
+}
+
+fn connection(record: &Record) -> Option<Connection> {
+    let (topic, message_type, md5sum, definition) = match record.message {
+        Message::ScanData(_) | Message::FusedScanData(_) | Message::ScalaScanData(_) => (
+            match record.data_type {
+                DataType::FusedScanData => "/lux/fused_scan",
+                DataType::ScalaScanData => "/lux/scala_scan",
+                DataType::FloatScanData => "/lux/float_scan",
+                _ => "/lux/scan",
+            },
+            "sensor_msgs/PointCloud2",
+            "1158d486dd51d683ce2f1be655c3c181",
+            format!("{}{}", POINT_CLOUD2_DEFINITION, HEADER_DEFINITION),
+        ),
+        Message::ObjectData(_) => (
+            "/lux/objects",
+            "visualization_msgs/MarkerArray",
+            "d155b9ce5188fbaf89745847fd5882d7",
+            format!("{}{}{}", MARKER_ARRAY_DEFINITION, HEADER_DEFINITION, VECTOR3_DEFINITION),
+        ),
+        Message::FusedObjectData(_) => (
+            "/lux/fused_objects",
+            "visualization_msgs/MarkerArray",
+            "d155b9ce5188fbaf89745847fd5882d7",
+            format!("{}{}{}", MARKER_ARRAY_DEFINITION, HEADER_DEFINITION, VECTOR3_DEFINITION),
+        ),
+        Message::MovementData(_) => (
+            "/lux/ego_motion",
+            "geometry_msgs/TwistStamped",
+            "98d34b0043a2093cf9d9345ab6eef12e",
+            format!("{}{}{}", TWIST_STAMPED_DEFINITION, HEADER_DEFINITION, VECTOR3_DEFINITION),
+        ),
+        _ => return None,
+    };
+    // "/lux/<source>/..." like the foxglove topics
+    let topic = match record.source {
+        Some(ref source) => topic.replacen("/lux/", &format!("/lux/{}/", source), 1),
+        None => topic.to_string(),
+    };
+    Some(Connection {
+        topic,
+        message_type,
+        md5sum,
+        definition,
+    })
+}
+
+fn put_field(buffer: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buffer.write_u32::<LittleEndian>((name.len() + 1 + value.len()) as u32).unwrap();
+    buffer.extend_from_slice(name.as_bytes());
+    buffer.push(b'=');
+    buffer.extend_from_slice(value);
+}
+
+fn put_record(buffer: &mut Vec<u8>, header: &[u8], data: &[u8]) {
+    buffer.write_u32::<LittleEndian>(header.len() as u32).unwrap();
+    buffer.extend_from_slice(header);
+    buffer.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+    buffer.extend_from_slice(data);
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    let mut bytes = [0; 4];
+    (&mut bytes[..]).write_u32::<LittleEndian>(value).unwrap();
+    bytes
+}
+
+fn u64_bytes(value: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    (&mut bytes[..]).write_u64::<LittleEndian>(value).unwrap();
+    bytes
+}
+
+fn ros_time(ntp_time: &NtpTime) -> Time {
+    let nanos = ntp_time.unix_nanos();
+    ((nanos / 1000000000) as u32, (nanos % 1000000000) as u32)
+}
+
+fn time_bytes(time: Time) -> [u8; 8] {
+    u64_bytes(((time.1 as u64) << 32) | (time.0 as u64))
+}
+
+fn put_string(buffer: &mut Vec<u8>, text: &str) {
+    buffer.write_u32::<LittleEndian>(text.len() as u32).unwrap();
+    buffer.extend_from_slice(text.as_bytes());
+}
+
+fn put_header(buffer: &mut Vec<u8>, seq: u32, stamp: Time) {
+    buffer.write_u32::<LittleEndian>(seq).unwrap();
+    buffer.write_u32::<LittleEndian>(stamp.0).unwrap();
+    buffer.write_u32::<LittleEndian>(stamp.1).unwrap();
+    put_string(buffer, "lux");
+}
+
+fn point_cloud2(scan: &Scan, seq: u32) -> Vec<u8> {
+    let point_step: u32 = 18;
+    let fields: [(&str, u32, u8); 6] = [
+        ("x", 0, FLOAT32),
+        ("y", 4, FLOAT32),
+        ("z", 8, FLOAT32),
+        ("intensity", 12, FLOAT32),
+        ("layer", 16, UINT8),
+        ("echo", 17, UINT8),
+    ];
+    let width = scan.points.len() as u32;
+    let mut buffer = Vec::new();
+    put_header(&mut buffer, seq, ros_time(&scan.start_time));
+    buffer.write_u32::<LittleEndian>(1).unwrap();
+    buffer.write_u32::<LittleEndian>(width).unwrap();
+    buffer.write_u32::<LittleEndian>(fields.len() as u32).unwrap();
+    for (name, offset, datatype) in fields.iter() {
+        put_string(&mut buffer, name);
+        buffer.write_u32::<LittleEndian>(*offset).unwrap();
+        buffer.write_u8(*datatype).unwrap();
+        buffer.write_u32::<LittleEndian>(1).unwrap();
+    }
+    buffer.write_u8(0).unwrap();
+    buffer.write_u32::<LittleEndian>(point_step).unwrap();
+    buffer.write_u32::<LittleEndian>(point_step * width).unwrap();
+    buffer.write_u32::<LittleEndian>(point_step * width).unwrap();
+    for point in &scan.points {
+        buffer.write_f32::<LittleEndian>(point.x).unwrap();
+        buffer.write_f32::<LittleEndian>(point.y).unwrap();
+        buffer.write_f32::<LittleEndian>(point.z).unwrap();
+        buffer.write_f32::<LittleEndian>(point.echo_pulse_width as f32).unwrap();
+        buffer.write_u8(point.layer).unwrap();
+        buffer.write_u8(point.echo).unwrap();
+    }
+    buffer.write_u8(1).unwrap();
+    buffer
+}
+
+struct Marker<'a> {
+    namespace: &'a str,
+    id: i32,
+    marker_type: i32,
+    // x, y, yaw
+    pose: (f64, f64, f64),
+    scale: (f64, f64, f64),
+    text: &'a str,
+}
+
+fn put_marker(buffer: &mut Vec<u8>, seq: u32, stamp: Time, marker: &Marker) {
+    put_header(buffer, seq, stamp);
+    put_string(buffer, marker.namespace);
+    buffer.write_i32::<LittleEndian>(marker.id).unwrap();
+    buffer.write_i32::<LittleEndian>(marker.marker_type).unwrap();
+    // add
+    buffer.write_i32::<LittleEndian>(0).unwrap();
+    let (x, y, yaw) = marker.pose;
+    for value in [x, y, 0.0, 0.0, 0.0, (yaw / 2.0).sin(), (yaw / 2.0).cos()].iter() {
+        buffer.write_f64::<LittleEndian>(*value).unwrap();
+    }
+    for value in [marker.scale.0, marker.scale.1, marker.scale.2].iter() {
+        buffer.write_f64::<LittleEndian>(*value).unwrap();
+    }
+    for value in [1.0, 0.6, 0.0, 0.5].iter() {
+        buffer.write_f32::<LittleEndian>(*value).unwrap();
+    }
+    // lifetime of 0.2 s
+    buffer.write_i32::<LittleEndian>(0).unwrap();
+    buffer.write_i32::<LittleEndian>(200000000).unwrap();
+    buffer.write_u8(0).unwrap();
+    buffer.write_u32::<LittleEndian>(0).unwrap();
+    buffer.write_u32::<LittleEndian>(0).unwrap();
+    put_string(buffer, marker.text);
+    put_string(buffer, "");
+    buffer.write_u8(0).unwrap();
+}
+
+fn marker_array(object_data: &ObjectData, seq: u32) -> Vec<u8> {
+    let stamp = ros_time(&object_data.scan_start_timestamp);
+    let mut markers = 0;
+    let mut buffer = Vec::new();
+    for object_info in &object_data.objects_vec {
+        // object positions and sizes are in cm
+        let x = (object_info.object_box_center.position_x as f64) / 100.0;
+        let y = (object_info.object_box_center.position_y as f64) / 100.0;
+        let id = object_info.object_id as i32;
+        let marker = Marker {
+            namespace: "objects",
+            id,
+            marker_type: CUBE,
+            pose: (x, y, object_info.orientation()),
+            scale: (
+                (object_info.object_box_size.size_x as f64) / 100.0,
+                (object_info.object_box_size.size_y as f64) / 100.0,
+                1.5,
+            ),
+            text: "",
+        };
+        put_marker(&mut buffer, seq, stamp, &marker);
+        let text = id.to_string();
+        let marker = Marker {
+            namespace: "object_ids",
+            id,
+            marker_type: TEXT_VIEW_FACING,
+            pose: (x, y, 0.0),
+            scale: (0.0, 0.0, 1.0),
+            text: &text,
+        };
+        put_marker(&mut buffer, seq, stamp, &marker);
+        markers += 2;
+
+        let velocity_x = (object_info.absolute_velocity.position_x as f64) / 100.0;
+        let velocity_y = (object_info.absolute_velocity.position_y as f64) / 100.0;
+        let speed = velocity_x.hypot(velocity_y);
+        if speed > 0.0 {
+            let marker = Marker {
+                namespace: "velocities",
+                id,
+                marker_type: ARROW,
+                pose: (x, y, velocity_y.atan2(velocity_x)),
+                scale: (speed, 0.1, 0.1),
+                text: "",
+            };
+            put_marker(&mut buffer, seq, stamp, &marker);
+            markers += 1;
+        }
+    }
+    let mut message = Vec::new();
+    message.write_u32::<LittleEndian>(markers).unwrap();
+    message.extend_from_slice(&buffer);
+    message
+}
+
+fn twist_stamped(movement_data: &MovementData, seq: u32) -> Vec<u8> {
+    // velocity in 0.01 m/s, yaw difference in 0.0001 rad over time difference in ms
+    let velocity = (movement_data.longitudinal_velocity as f64) / 100.0;
+    let yaw_rate = if movement_data.time_diff > 0 {
+        (movement_data.yaw_diff as f64) / 10000.0 / ((movement_data.time_diff as f64) / 1000.0)
+    } else {
+        0.0
+    };
+    let mut buffer = Vec::new();
+    put_header(&mut buffer, seq, ros_time(&movement_data.timestamp));
+    for value in [velocity, 0.0, 0.0, 0.0, 0.0, yaw_rate].iter() {
+        buffer.write_f64::<LittleEndian>(*value).unwrap();
+    }
+    buffer
+}
+
+// padded with spaces so that it can be rewritten in place on finish
+fn bag_header(index_position: u64, connection_count: u32, chunk_count: u32) -> Vec<u8> {
+    let mut header = Vec::new();
+    put_field(&mut header, "op", &[OP_BAG_HEADER]);
+    put_field(&mut header, "index_pos", &u64_bytes(index_position));
+    put_field(&mut header, "conn_count", &u32_bytes(connection_count));
+    put_field(&mut header, "chunk_count", &u32_bytes(chunk_count));
+    let padding = vec![b' '; BAG_HEADER_LENGTH - header.len() - 8];
+    let mut record = Vec::new();
+    put_record(&mut record, &header, &padding);
+    record
+}
+
+fn chunk_info(chunk_position: u64, start_time: Time, end_time: Time, counts: &BTreeMap<u32, u32>) -> Vec<u8> {
+    let mut header = Vec::new();
+    put_field(&mut header, "op", &[OP_CHUNK_INFO]);
+    put_field(&mut header, "ver", &u32_bytes(1));
+    put_field(&mut header, "chunk_pos", &u64_bytes(chunk_position));
+    put_field(&mut header, "start_time", &time_bytes(start_time));
+    put_field(&mut header, "end_time", &time_bytes(end_time));
+    put_field(&mut header, "count", &u32_bytes(counts.len() as u32));
+    let mut data = Vec::new();
+    for (connection_id, count) in counts {
+        data.write_u32::<LittleEndian>(*connection_id).unwrap();
+        data.write_u32::<LittleEndian>(*count).unwrap();
+    }
+    let mut record = Vec::new();
+    put_record(&mut record, &header, &data);
+    record
+}
+
+pub struct BagWriter<W: Write + Seek> {
+    writer: W,
+    position: u64,
+    connections: HashMap<String, u32>,
+    connection_records: Vec<Vec<u8>>,
+    chunk: Vec<u8>,
+    chunk_start_time: Time,
+    chunk_end_time: Time,
+    indexes: BTreeMap<u32, Vec<(Time, u32)>>,
+    chunk_infos: Vec<Vec<u8>>,
+    seq: u32,
+}
+
+impl<W: Write + Seek> BagWriter<W> {
+    pub fn new(writer: W) -> io::Result<BagWriter<W>> {
+        let mut bag_writer = BagWriter {
+            writer,
+            position: 0,
+            connections: HashMap::new(),
+            connection_records: Vec::new(),
+            chunk: Vec::new(),
+            chunk_start_time: (u32::MAX, 0),
+            chunk_end_time: (0, 0),
+            indexes: BTreeMap::new(),
+            chunk_infos: Vec::new(),
+            seq: 0,
+        };
+        bag_writer.write_all(VERSION)?;
+        bag_writer.write_all(&bag_header(0, 0, 0))?;
+        Ok(bag_writer)
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn connection_id(&mut self, connection: &Connection) -> u32 {
+        if let Some(id) = self.connections.get(&connection.topic) {
+            return *id;
+        }
+        let id = self.connections.len() as u32;
+        let mut header = Vec::new();
+        put_field(&mut header, "op", &[OP_CONNECTION]);
+        put_field(&mut header, "conn", &u32_bytes(id));
+        put_field(&mut header, "topic", connection.topic.as_bytes());
+        let mut data = Vec::new();
+        put_field(&mut data, "topic", connection.topic.as_bytes());
+        put_field(&mut data, "type", connection.message_type.as_bytes());
+        put_field(&mut data, "md5sum", connection.md5sum.as_bytes());
+        put_field(&mut data, "message_definition", connection.definition.as_bytes());
+        let mut record = Vec::new();
+        put_record(&mut record, &header, &data);
+        self.chunk.extend_from_slice(&record);
+        self.connection_records.push(record);
+        self.connections.insert(connection.topic.clone(), id);
+        id
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk_position = self.position;
+        let mut header = Vec::new();
+        put_field(&mut header, "op", &[OP_CHUNK]);
+        put_field(&mut header, "compression", b"none");
+        put_field(&mut header, "size", &u32_bytes(self.chunk.len() as u32));
+        let mut record = Vec::new();
+        put_record(&mut record, &header, &self.chunk);
+        self.write_all(&record)?;
+
+        let mut counts = BTreeMap::new();
+        let indexes = ::std::mem::take(&mut self.indexes);
+        for (connection_id, entries) in indexes {
+            counts.insert(connection_id, entries.len() as u32);
+            let mut header = Vec::new();
+            put_field(&mut header, "op", &[OP_INDEX_DATA]);
+            put_field(&mut header, "ver", &u32_bytes(1));
+            put_field(&mut header, "conn", &u32_bytes(connection_id));
+            put_field(&mut header, "count", &u32_bytes(entries.len() as u32));
+            let mut data = Vec::new();
+            for (time, offset) in entries {
+                data.extend_from_slice(&time_bytes(time));
+                data.write_u32::<LittleEndian>(offset)?;
+            }
+            let mut record = Vec::new();
+            put_record(&mut record, &header, &data);
+            self.write_all(&record)?;
+        }
+        let chunk_info = chunk_info(chunk_position, self.chunk_start_time, self.chunk_end_time, &counts);
+        self.chunk_infos.push(chunk_info);
+
+        self.chunk.clear();
+        self.chunk_start_time = (u32::MAX, 0);
+        self.chunk_end_time = (0, 0);
+        Ok(())
+    }
+}
+
+impl RecordWriter for BagWriter<BufWriter<File>> {
+    // data types without a standard ros message are skipped
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let connection = match connection(record) {
+            Some(connection) => connection,
+            None => return Ok(()),
+        };
+        let connection_id = self.connection_id(&connection);
+        let data = match record.message {
+            Message::ScanData(_) | Message::FusedScanData(_) | Message::ScalaScanData(_) => match record.message.scan() {
+                Some(scan) => point_cloud2(&scan, self.seq),
+                None => return Ok(()),
+            },
+            Message::ObjectData(ref object_data) => marker_array(object_data, self.seq),
+            Message::FusedObjectData(ref object_data) => marker_array(&object_data.object_data(), self.seq),
+            Message::MovementData(ref movement_data) => twist_stamped(movement_data, self.seq),
+            _ => return Ok(()),
+        };
+        self.seq = self.seq.wrapping_add(1);
+
+        let time = ros_time(&record.header.ntp_time);
+        let offset = self.chunk.len() as u32;
+        let mut header = Vec::new();
+        put_field(&mut header, "op", &[OP_MESSAGE_DATA]);
+        put_field(&mut header, "conn", &u32_bytes(connection_id));
+        put_field(&mut header, "time", &time_bytes(time));
+        put_record(&mut self.chunk, &header, &data);
+
+        self.indexes.entry(connection_id).or_default().push((time, offset));
+        self.chunk_start_time = self.chunk_start_time.min(time);
+        self.chunk_end_time = self.chunk_end_time.max(time);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, fsync: bool) -> io::Result<()> {
+        if self.chunk.len() >= MIN_CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+        self.writer.flush()?;
+        if fsync {
+            self.writer.get_ref().sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.flush_chunk()?;
+        let index_position = self.position;
+        let connection_records = ::std::mem::take(&mut self.connection_records);
+        for record in connection_records {
+            self.write_all(&record)?;
+        }
+        let chunk_infos = ::std::mem::take(&mut self.chunk_infos);
+        for record in &chunk_infos {
+            self.write_all(record)?;
+        }
+        self.chunk_infos = chunk_infos;
+        let bag_header = bag_header(index_position, self.connections.len() as u32, self.chunk_infos.len() as u32);
+        self.writer.seek(SeekFrom::Start(VERSION.len() as u64))?;
+        self.writer.write_all(&bag_header)?;
+        self.writer.flush()
+    }
+}
+
+// header, data and the position of the next record
+fn read_record(data: &[u8], position: usize) -> Option<(&[u8], &[u8], usize)> {
+    let read_len = |position: usize| -> Option<usize> {
+        if position + 4 > data.len() {
+            return None;
+        }
+        let len = LittleEndian::read_u32(&data[position..position + 4]) as usize;
+        if len > data.len() - position - 4 {
+            return None;
+        }
+        Some(len)
+    };
+    let header_len = read_len(position)?;
+    let data_position = position + 4 + header_len;
+    let data_len = read_len(data_position)?;
+    let next = data_position + 4 + data_len;
+    Some((&data[position + 4..data_position], &data[data_position + 4..next], next))
+}
+
+fn read_field<'a>(header: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let mut position = 0;
+    while position + 4 <= header.len() {
+        let len = LittleEndian::read_u32(&header[position..position + 4]) as usize;
+        let field = &header[position + 4..(position + 4 + len).min(header.len())];
+        if field.len() > name.len() && field.starts_with(name.as_bytes()) && field[name.len()] == b'=' {
+            return Some(&field[name.len() + 1..]);
+        }
+        position += 4 + len;
+    }
+    None
+}
+
+fn read_op(header: &[u8]) -> Option<u8> {
+    read_field(header, "op").and_then(|op| op.first().cloned())
+}
+
+// truncates a bag that was not closed after its last complete chunk and
+// rebuilds the connection and chunk info records from the chunks
+pub fn repair(data: &mut Vec<u8>) -> io::Result<bool> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if !data.starts_with(VERSION) {
+        return Err(invalid("not a rosbag v2.0 file"));
+    }
+    let (header, _, mut position) = read_record(data, VERSION.len()).ok_or_else(|| invalid("no bag header"))?;
+    if position != VERSION.len() + BAG_HEADER_LENGTH {
+        return Err(invalid("unexpected bag header length"));
+    }
+    let index_position = read_field(header, "index_pos").map_or(0, LittleEndian::read_u64) as usize;
+    if index_position >= position && index_position <= data.len() {
+        let mut next = index_position;
+        while let Some((_, _, record_end)) = read_record(data, next) {
+            next = record_end;
+        }
+        if next == data.len() {
+            return Ok(false);
+        }
+    }
+
+    let mut connections = BTreeMap::new();
+    let mut chunk_infos = Vec::new();
+    while let Some((header, chunk, chunk_end)) = read_record(data, position) {
+        if read_op(header) != Some(OP_CHUNK) || read_field(header, "compression") != Some(b"none") {
+            break;
+        }
+        let mut chunk_connections = Vec::new();
+        let mut counts = BTreeMap::new();
+        let mut start_time = (u32::MAX, 0);
+        let mut end_time = (0, 0);
+        let mut offset = 0;
+        while let Some((header, _, next)) = read_record(chunk, offset) {
+            let connection_id = read_field(header, "conn").map_or(0, LittleEndian::read_u32);
+            match read_op(header) {
+                Some(OP_CONNECTION) => chunk_connections.push((connection_id, chunk[offset..next].to_vec())),
+                Some(OP_MESSAGE_DATA) => {
+                    *counts.entry(connection_id).or_insert(0) += 1;
+                    if let Some(time) = read_field(header, "time") {
+                        let time = (LittleEndian::read_u32(&time[0..4]), LittleEndian::read_u32(&time[4..8]));
+                        start_time = start_time.min(time);
+                        end_time = end_time.max(time);
+                    }
+                },
+                _ => {},
+            }
+            offset = next;
+        }
+        // every chunk is followed by one index data record per connection
+        let mut next = chunk_end;
+        let mut indexes = 0;
+        while indexes < counts.len() {
+            match read_record(data, next) {
+                Some((header, _, record_end)) if read_op(header) == Some(OP_INDEX_DATA) => next = record_end,
+                _ => break,
+            }
+            indexes += 1;
+        }
+        if offset != chunk.len() || indexes != counts.len() {
+            break;
+        }
+        for (connection_id, record) in chunk_connections {
+            connections.entry(connection_id).or_insert(record);
+        }
+        chunk_infos.push(chunk_info(position as u64, start_time, end_time, &counts));
+        position = next;
+    }
+
+    data.truncate(position);
+    for record in connections.values() {
+        data.extend_from_slice(record);
+    }
+    for record in &chunk_infos {
+        data.extend_from_slice(record);
+    }
+    let bag_header = bag_header(position as u64, connections.len() as u32, chunk_infos.len() as u32);
+    data[VERSION.len()..VERSION.len() + BAG_HEADER_LENGTH].copy_from_slice(&bag_header);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::BufWriter;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::{
+        bag_header, read_field, read_op, read_record, repair, BagWriter, BAG_HEADER_LENGTH, OP_BAG_HEADER, OP_CHUNK,
+        OP_CHUNK_INFO, OP_CONNECTION, VERSION,
+    };
+    use fixtures::{self, scan_data, temp_path};
+    use output::RecordWriter;
+    use stream::Record;
+
+    fn record(data_type: u16, payload: &[u8], source: Option<&str>) -> Record {
+        let mut record = fixtures::record(data_type, payload);
+        record.source = source.map(str::to_string);
+        record
+    }
+
+    // the opcodes of the records after the index position and the counts
+    // of the bag header
+    fn index(data: &[u8]) -> (Vec<u8>, u32, u32) {
+        assert!(data.starts_with(VERSION));
+        let (header, _, _) = read_record(data, VERSION.len()).unwrap();
+        assert_eq!(read_op(header), Some(OP_BAG_HEADER));
+        let field = |name| read_field(header, name).map(LittleEndian::read_u32).unwrap();
+        let mut position = read_field(header, "index_pos").map(LittleEndian::read_u64).unwrap() as usize;
+        let mut opcodes = Vec::new();
+        while let Some((header, _, next)) = read_record(data, position) {
+            opcodes.push(read_op(header).unwrap());
+            position = next;
+        }
+        assert_eq!(position, data.len());
+        (opcodes, field("conn_count"), field("chunk_count"))
+    }
+
+    #[test]
+    fn connections_and_chunks() {
+        let path = temp_path("connections.bag");
+        let mut writer = Box::new(BagWriter::new(BufWriter::new(File::create(&path).unwrap())).unwrap());
+        writer.write(&record(0x2202, &scan_data(0.0), Some("front"))).unwrap();
+        writer.write(&record(0x2805, &[0; 38], None)).unwrap();
+        writer.flush_chunk().unwrap();
+        writer.write(&record(0x2202, &scan_data(0.0), Some("front"))).unwrap();
+        writer.write(&record(0x2202, &scan_data(0.0), Some("rear"))).unwrap();
+        // no ros message for error data
+        writer.write(&record(0x2030, &[0; 8], None)).unwrap();
+        writer.finish().unwrap();
+        let mut data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (opcodes, connections, chunks) = index(&data);
+        assert_eq!((connections, chunks), (3, 2));
+        assert_eq!(opcodes, vec![OP_CONNECTION, OP_CONNECTION, OP_CONNECTION, OP_CHUNK_INFO, OP_CHUNK_INFO]);
+        let (header, _, next) = read_record(&data, VERSION.len()).unwrap();
+        let (chunk, _, _) = read_record(&data, next).unwrap();
+        assert_eq!(read_op(chunk), Some(OP_CHUNK));
+        assert!(!repair(&mut data.clone()).unwrap());
+
+        // a bag that was not closed, the index is rebuilt from the chunks
+        let index_position = read_field(header, "index_pos").map(LittleEndian::read_u64).unwrap() as usize;
+        data.truncate(index_position);
+        data[VERSION.len()..VERSION.len() + BAG_HEADER_LENGTH].copy_from_slice(&bag_header(0, 0, 0));
+        assert!(repair(&mut data).unwrap());
+        let (opcodes, connections, chunks) = index(&data);
+        assert_eq!((connections, chunks), (3, 2));
+        assert_eq!(opcodes.len(), 5);
+    }
+}
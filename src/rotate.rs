@@ -0,0 +1,241 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use compression;
+use output::{self, RecordWriter};
+use stream::Record;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_seconds: Option<f64>,
+    pub max_scans: Option<u64>,
+}
+
+impl Rotation {
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes.is_some() || self.max_seconds.is_some() || self.max_scans.is_some()
+    }
+}
+
+// "drive.ndjson.zst" is rotated into "drive_20170801T120000_0000.ndjson.zst",
+// named by the timestamp of the first record in each file. every file is
+// closed properly so each of them can be read on its own. without a rotation
+// policy everything goes into the given path.
+pub struct RotatingWriter {
+    path: PathBuf,
+    format: String,
+    rotation: Rotation,
+    writer: Option<Box<dyn RecordWriter>>,
+    current: PathBuf,
+    sequence: u32,
+    start_time: f64,
+    scans: u64,
+    files: Vec<PathBuf>,
+}
+
+impl RotatingWriter {
+    pub fn new(path: &Path, format: &str, rotation: Rotation) -> RotatingWriter {
+        RotatingWriter {
+            path: path.to_path_buf(),
+            format: format.to_string(),
+            rotation,
+            writer: None,
+            current: PathBuf::new(),
+            sequence: 0,
+            start_time: 0.0,
+            scans: 0,
+            files: Vec::new(),
+        }
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn closed_files(&self) -> &[PathBuf] {
+        match self.writer {
+            Some(_) => &self.files[..self.files.len() - 1],
+            None => &self.files,
+        }
+    }
+
+    // what has reached the disk so far, buffered data is not counted
+    pub fn bytes(&self) -> u64 {
+        self.files.iter().map(|file| fs::metadata(file).map_or(0, |metadata| metadata.len())).sum()
+    }
+
+    fn file_name(&self, record: &Record) -> PathBuf {
+        if !self.rotation.is_enabled() {
+            return self.path.clone();
+        }
+        let inner = compression::strip_extension(&self.path);
+        let stem = inner.file_stem().and_then(OsStr::to_str).unwrap_or("lux");
+        let mut name = format!(
+            "{}_{}_{:04}",
+            stem,
+            record.header.ntp_time.datetime().format("%Y%m%dT%H%M%S"),
+            self.sequence
+        );
+        if let Some(extension) = inner.extension().and_then(OsStr::to_str) {
+            name = format!("{}.{}", name, extension);
+        }
+        if inner != self.path {
+            if let Some(extension) = self.path.extension().and_then(OsStr::to_str) {
+                name = format!("{}.{}", name, extension);
+            }
+        }
+        self.path.with_file_name(name)
+    }
+
+    fn is_full(&self, record: &Record) -> io::Result<bool> {
+        if let Some(max_scans) = self.rotation.max_scans {
+            if self.scans >= max_scans {
+                return Ok(true);
+            }
+        }
+        if let Some(max_seconds) = self.rotation.max_seconds {
+            if record.timestamp - self.start_time >= max_seconds {
+                return Ok(true);
+            }
+        }
+        if let Some(max_bytes) = self.rotation.max_bytes {
+            if fs::metadata(&self.current)?.len() >= max_bytes {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn close(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+            self.sequence += 1;
+        }
+        Ok(())
+    }
+}
+
+impl RecordWriter for RotatingWriter {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.writer.is_some() && self.is_full(record)? {
+            self.close()?;
+        }
+        if self.writer.is_none() {
+            self.current = self.file_name(record);
+            self.writer = Some(output::create(&self.current, &self.format)?);
+            self.files.push(self.current.clone());
+            self.start_time = record.timestamp;
+            self.scans = 0;
+        }
+        if record.data_type.is_scan() {
+            self.scans += 1;
+        }
+        self.writer.as_mut().unwrap().write(record)
+    }
+
+    fn flush(&mut self, fsync: bool) -> io::Result<()> {
+        match self.writer {
+            Some(ref mut writer) => writer.flush(fsync),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::{Rotation, RotatingWriter};
+    use fixtures::{record_at, scan_data, temp_path};
+    use output::RecordWriter;
+
+    // the names of the files and the records in each of them
+    fn rotate(name: &str, rotation: Rotation, times: &[f64], flush: bool) -> Vec<(String, usize)> {
+        let dir = temp_path(name);
+        fs::create_dir_all(&dir).unwrap();
+        let mut writer = Box::new(RotatingWriter::new(&dir.join("drive.ndjson"), "ndjson", rotation));
+        for &seconds in times {
+            writer.write(&record_at(0x2202, &scan_data(seconds), seconds)).unwrap();
+            if flush {
+                writer.flush(false).unwrap();
+            }
+        }
+        let files: Vec<PathBuf> = writer.files().to_vec();
+        writer.finish().unwrap();
+        let files = files
+            .iter()
+            .map(|file| {
+                let name = file.file_name().unwrap().to_string_lossy().into_owned();
+                (name, fs::read_to_string(file).unwrap().lines().count())
+            })
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        files
+    }
+
+    #[test]
+    fn without_rotation() {
+        let files = rotate("rotate-none", Rotation::default(), &[0.0, 1.0, 2.0], false);
+        assert_eq!(files, vec![("drive.ndjson".to_string(), 3)]);
+    }
+
+    #[test]
+    fn by_scans() {
+        let rotation = Rotation {
+            max_scans: Some(2),
+            ..Default::default()
+        };
+        let files = rotate("rotate-scans", rotation, &[0.0, 0.1, 0.2, 0.3, 0.4], false);
+        assert_eq!(
+            files,
+            vec![
+                ("drive_20200601T113320_0000.ndjson".to_string(), 2),
+                ("drive_20200601T113320_0001.ndjson".to_string(), 2),
+                ("drive_20200601T113320_0002.ndjson".to_string(), 1),
+            ]
+        );
+    }
+
+    // a file is full once a record is at least `max_seconds` after its first
+    #[test]
+    fn by_seconds() {
+        let rotation = Rotation {
+            max_seconds: Some(1.0),
+            ..Default::default()
+        };
+        let files = rotate("rotate-seconds", rotation, &[0.0, 0.5, 0.999, 1.0, 1.5, 2.0], false);
+        let counts: Vec<usize> = files.iter().map(|&(_, count)| count).collect();
+        assert_eq!(counts, vec![3, 2, 1]);
+        assert_eq!(files[1].0, "drive_20200601T113321_0001.ndjson");
+    }
+
+    #[test]
+    fn by_bytes() {
+        let rotation = Rotation {
+            max_bytes: Some(1),
+            ..Default::default()
+        };
+        let files = rotate("rotate-bytes", rotation, &[0.0, 0.1, 0.2], true);
+        let counts: Vec<usize> = files.iter().map(|&(_, count)| count).collect();
+        assert_eq!(counts, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn compressed_names() {
+        let rotation = Rotation {
+            max_scans: Some(1),
+            ..Default::default()
+        };
+        let writer = RotatingWriter::new(&PathBuf::from("drive.ndjson.zst"), "ndjson", rotation);
+        let name = writer.file_name(&record_at(0x2202, &scan_data(0.0), 0.0));
+        assert_eq!(name, PathBuf::from("drive_20200601T113320_0000.ndjson.zst"));
+    }
+}
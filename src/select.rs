@@ -0,0 +1,201 @@
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use compression;
+use output::{self, RecordWriter};
+use rotate::{Rotation, RotatingWriter};
+use stream::Record;
+use DataType;
+
+const FORMATS: &[&str] = &["yaml", "ndjson", "mcap", "bag"];
+
+fn data_types(name: &str) -> Option<Vec<DataType>> {
+    match name {
+        "scan" => Some(vec![
+            DataType::ScanData,
+            DataType::FusedScanData,
+            DataType::ScalaScanData,
+            DataType::FloatScanData,
+        ]),
+        "object" => Some(vec![DataType::ObjectData, DataType::FusedObjectData]),
+        "ego" => Some(vec![DataType::MovementData, DataType::EgoMotionData]),
+        "error" => Some(vec![DataType::Error]),
+        "info" => Some(vec![DataType::SensorInfo]),
+        "command" => Some(vec![DataType::Command, DataType::CommandReply]),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Selection {
+    name: String,
+    data_types: Vec<DataType>,
+    format: Option<String>,
+}
+
+// "scan,object" records both into the output file, "scan:mcap,object" puts
+// the scans into a separate "<output>.scan.mcap" file instead
+pub fn parse(text: &str) -> Result<Vec<Selection>, String> {
+    text.split(',')
+        .map(|item| {
+            let mut parts = item.trim().splitn(2, ':');
+            let name = parts.next().unwrap_or("");
+            let data_types = data_types(name).ok_or_else(|| {
+                format!("unknown data type '{}', expected scan, object, ego, error, info or command", name)
+            })?;
+            let format = parts.next().map(str::to_string);
+            if let Some(ref format) = format {
+                let mut extensions = format.splitn(2, '.');
+                let base = extensions.next().unwrap_or("");
+                let compression = extensions.next();
+                if !FORMATS.contains(&base) || compression.is_some_and(|extension| extension != "zst" && extension != "gz") {
+                    return Err(format!("unknown format '{}' for {}, expected yaml, ndjson, mcap or bag", format, name));
+                }
+                if base == "bag" && compression.is_some() {
+                    return Err("bag files cannot be compressed".to_string());
+                }
+            }
+            Ok(Selection {
+                name: name.to_string(),
+                data_types,
+                format,
+            })
+        })
+        .collect()
+}
+
+// routes every selected data type to its output, the rest is dropped
+pub struct SplitWriter {
+    path: PathBuf,
+    outputs: Vec<(Vec<DataType>, RotatingWriter)>,
+}
+
+impl SplitWriter {
+    pub fn new(path: &Path, selections: &[Selection], rotation: Rotation) -> SplitWriter {
+        let mut outputs = Vec::new();
+        let mut data_types = Vec::new();
+        for selection in selections {
+            match selection.format {
+                Some(ref format) => {
+                    let inner = compression::strip_extension(path);
+                    let stem = inner.file_stem().and_then(OsStr::to_str).unwrap_or("lux");
+                    let file = path.with_file_name(format!("{}.{}.{}", stem, selection.name, format));
+                    let writer = RotatingWriter::new(&file, output::format_from_path(&file).unwrap_or("idc"), rotation);
+                    outputs.push((selection.data_types.clone(), writer));
+                },
+                None => data_types.extend(selection.data_types.iter().cloned()),
+            }
+        }
+        if !data_types.is_empty() {
+            outputs.insert(0, (data_types, RotatingWriter::new(path, output::format_from_path(path).unwrap_or("idc"), rotation)));
+        }
+        SplitWriter {
+            path: path.to_path_buf(),
+            outputs,
+        }
+    }
+
+    pub fn accepts(&self, data_type: &DataType) -> bool {
+        self.outputs.iter().any(|(data_types, _)| data_types.contains(data_type))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.outputs.iter().flat_map(|(_, writer)| writer.files().to_vec()).collect()
+    }
+
+    pub fn closed_files(&self) -> Vec<PathBuf> {
+        self.outputs.iter().flat_map(|(_, writer)| writer.closed_files().to_vec()).collect()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.outputs.iter().map(|(_, writer)| writer.bytes()).sum()
+    }
+
+    pub fn close(&mut self) -> io::Result<()> {
+        for (_, writer) in &mut self.outputs {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordWriter for SplitWriter {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        for (data_types, writer) in &mut self.outputs {
+            if data_types.contains(&record.data_type) {
+                writer.write(record)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, fsync: bool) -> io::Result<()> {
+        for (_, writer) in &mut self.outputs {
+            writer.flush(fsync)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{parse, SplitWriter};
+    use fixtures::{object_data, record, scan_data, temp_path};
+    use output::RecordWriter;
+    use rotate::Rotation;
+    use DataType;
+
+    fn error(text: &str) -> String {
+        parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn selections() {
+        let selections = parse("scan:mcap.zst, object,ego").unwrap();
+        assert_eq!(selections.len(), 3);
+        assert_eq!(selections[0].name, "scan");
+        assert_eq!(selections[0].format.as_deref(), Some("mcap.zst"));
+        assert!(selections[0].data_types.contains(&DataType::FloatScanData));
+        assert_eq!(selections[1].format, None);
+        assert_eq!(selections[2].data_types, vec![DataType::MovementData, DataType::EgoMotionData]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error("scan,points").starts_with("unknown data type 'points'"));
+        assert!(error("").starts_with("unknown data type ''"));
+        assert!(error("scan:pcd").starts_with("unknown format 'pcd' for scan"));
+        assert!(error("scan:yaml.xz").starts_with("unknown format 'yaml.xz' for scan"));
+        assert_eq!(error("scan:bag.zst"), "bag files cannot be compressed");
+        assert_eq!(error("object:bag.gz"), "bag files cannot be compressed");
+    }
+
+    #[test]
+    fn split_files() {
+        let dir = temp_path("split");
+        fs::create_dir_all(&dir).unwrap();
+        let selections = parse("scan:mcap,object").unwrap();
+        let mut writer = Box::new(SplitWriter::new(&dir.join("drive.ndjson.zst"), &selections, Rotation::default()));
+        assert!(writer.accepts(&DataType::ScanData));
+        assert!(writer.accepts(&DataType::ObjectData));
+        assert!(!writer.accepts(&DataType::Error));
+        writer.write(&record(0x2202, &scan_data(0.0))).unwrap();
+        writer.write(&record(0x2221, &object_data(&[1]))).unwrap();
+        writer.write(&record(0x2030, &[0; 8])).unwrap();
+        let files = writer.files();
+        writer.finish().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("drive.ndjson.zst"), dir.join("drive.scan.mcap")]);
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::Read;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use stream::Record;
+use {is_interrupted, receive_payload, stop_measure, sync_time, Header};
+
+// what the connection to a sensor reports to the recorder, with the index of
+// the sensor
+pub enum Event {
+    Record(usize, Record),
+    Log(usize, String),
+    // the reason the connection ended, the last event of a sensor
+    Closed(usize, String),
+}
+
+// sends the closing event when the connection thread ends, also when it
+// panics
+struct Closing {
+    index: usize,
+    events: Sender<Event>,
+    reason: Option<String>,
+}
+
+impl Drop for Closing {
+    fn drop(&mut self) {
+        let reason = self.reason.take().unwrap_or_else(|| "the connection thread panicked".to_string());
+        let _ = self.events.send(Event::Closed(self.index, reason));
+    }
+}
+
+pub struct Connection {
+    pub index: usize,
+    // tagged on every record
+    pub source: Option<String>,
+    // the stream of a fusion ecu carries several devices
+    pub split_devices: bool,
+    pub stream: TcpStream,
+    pub time_sync_interval: Option<f64>,
+    pub stop_measure: bool,
+}
+
+impl Connection {
+    // reads on a thread of its own until the connection ends or `running` is
+    // cleared, keeping the sensor time in sync
+    pub fn spawn(mut self, running: Arc<AtomicBool>, events: Sender<Event>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut closing = Closing {
+                index: self.index,
+                events: events.clone(),
+                reason: None,
+            };
+            self.sync_time(&events);
+            let reason = self.read(&running, &events);
+            if self.stop_measure {
+                if let Err(e) = stop_measure(&mut self.stream) {
+                    let _ = events.send(Event::Log(self.index, format!("cannot send StopMeasure: {}", e)));
+                }
+            }
+            closing.reason = Some(reason);
+        })
+    }
+
+    fn sync_time(&mut self, events: &Sender<Event>) {
+        if let Err(e) = sync_time(&mut self.stream) {
+            let _ = events.send(Event::Log(self.index, format!("cannot sync the time: {}", e)));
+        }
+    }
+
+    fn read(&mut self, running: &AtomicBool, events: &Sender<Event>) -> String {
+        let mut buffer: [u8; 24] = [0; 24];
+        let mut received = 0;
+        let mut last_time_sync = Instant::now();
+        while running.load(Ordering::SeqCst) {
+            // a header can arrive in pieces, so it is collected across reads
+            match self.stream.read(&mut buffer[received..]) {
+                Ok(0) => return "connection closed by the sensor".to_string(),
+                Ok(len) => received += len,
+                Err(ref e) if is_interrupted(e) => {},
+                Err(e) => return format!("connection lost: {}", e),
+            }
+            if received == 24 {
+                received = 0;
+                if BigEndian::read_u32(&buffer[0..4]) == 0xaffec0c2 {
+                    let size_of_message_data = BigEndian::read_u32(&buffer[8..12]);
+                    let payload: Vec<u8> = receive_payload(&mut self.stream, size_of_message_data);
+                    if payload.len() < size_of_message_data as usize {
+                        return "connection closed by the sensor".to_string();
+                    }
+                    // messages of unsupported data types are dropped
+                    if let Some(header) = Header::new(buffer) {
+                        let mut record = Record::new(header, payload.as_slice());
+                        record.source = self.source.clone();
+                        if self.split_devices {
+                            record.label_device();
+                        }
+                        if events.send(Event::Record(self.index, record)).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            if let Some(interval) = self.time_sync_interval {
+                if last_time_sync.elapsed().as_secs_f64() >= interval {
+                    self.sync_time(events);
+                    last_time_sync = Instant::now();
+                }
+            }
+        }
+        "recording stopped".to_string()
+    }
+}
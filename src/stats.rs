@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use stream::{Message, Record};
+use {ErrorData, NtpTime};
+
+// an object id not seen for this long starts a new track when it shows up again
+const TRACK_TIMEOUT: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Accumulator {
+    count: u64,
+    sum: f64,
+    sum_of_squares: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: f64) {
+        if self.count == 0 || value < self.min {
+            self.min = value;
+        }
+        if self.count == 0 || value > self.max {
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.sum_of_squares += value * value;
+    }
+
+    fn summary(&self) -> Option<Summary> {
+        if self.count == 0 {
+            return None;
+        }
+        let mean = self.sum / self.count as f64;
+        Some(Summary {
+            min: self.min,
+            mean,
+            max: self.max,
+            std_dev: (self.sum_of_squares / self.count as f64 - mean * mean).max(0.0).sqrt(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Summary {
+    min: f64,
+    mean: f64,
+    max: f64,
+    std_dev: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageCount {
+    count: u64,
+    rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Gap {
+    start: String,
+    end: String,
+    seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct ScanReport {
+    count: u64,
+    // hz, from the scan start times, none with a single scan
+    frequency: Option<f64>,
+    // milliseconds between two scan starts, the standard deviation is the jitter
+    period: Option<Summary>,
+    points_per_layer: BTreeMap<u8, Summary>,
+}
+
+#[derive(Debug, Serialize)]
+struct ObjectReport {
+    per_frame: Summary,
+    tracks: u64,
+    // seconds between the first and the last frame of a track
+    lifetime: Option<Summary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    start_time: Option<String>,
+    end_time: Option<String>,
+    duration: f64,
+    messages: BTreeMap<String, MessageCount>,
+    scans: Option<ScanReport>,
+    objects: Option<ObjectReport>,
+    gap_threshold: f64,
+    gaps: Vec<Gap>,
+    // occurrences of every set bit in the error data registers
+    errors: BTreeMap<String, u64>,
+    warnings: BTreeMap<String, u64>,
+}
+
+pub struct Stats {
+    gap_threshold: f64,
+    start: Option<NtpTime>,
+    last: Option<(f64, NtpTime)>,
+    counts: BTreeMap<String, u64>,
+    gaps: Vec<Gap>,
+    // per source, scans of different sensors interleave
+    last_scan_start: HashMap<Option<String>, f64>,
+    scans: u64,
+    scan_periods: Accumulator,
+    layer_points: [Accumulator; 16],
+    objects_per_frame: Accumulator,
+    // first and last time every object id of a source was seen
+    tracks: HashMap<(Option<String>, u16), (f64, f64)>,
+    finished_tracks: u64,
+    lifetimes: Accumulator,
+    errors: BTreeMap<String, u64>,
+    warnings: BTreeMap<String, u64>,
+}
+
+fn count_bits(counts: &mut BTreeMap<String, u64>, register: &str, value: u16) {
+    for bit in 0..16 {
+        if value & (1 << bit) != 0 {
+            *counts.entry(format!("{} bit {}", register, bit)).or_insert(0) += 1;
+        }
+    }
+}
+
+impl Stats {
+    pub fn new(gap_threshold: f64) -> Stats {
+        Stats {
+            gap_threshold,
+            start: None,
+            last: None,
+            counts: BTreeMap::new(),
+            gaps: Vec::new(),
+            last_scan_start: HashMap::new(),
+            scans: 0,
+            scan_periods: Accumulator::default(),
+            layer_points: [Accumulator::default(); 16],
+            objects_per_frame: Accumulator::default(),
+            tracks: HashMap::new(),
+            finished_tracks: 0,
+            lifetimes: Accumulator::default(),
+            errors: BTreeMap::new(),
+            warnings: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, record: &Record) {
+        let ntp_time = &record.header.ntp_time;
+        if self.start.is_none() {
+            self.start = Some(ntp_time.clone());
+        }
+        if let Some((timestamp, ref last)) = self.last {
+            if record.timestamp - timestamp > self.gap_threshold {
+                self.gaps.push(Gap {
+                    start: last.datetime().to_rfc3339(),
+                    end: ntp_time.datetime().to_rfc3339(),
+                    seconds: record.timestamp - timestamp,
+                });
+            }
+        }
+        self.last = Some((record.timestamp, ntp_time.clone()));
+        *self.counts.entry(format!("{:?}", record.data_type)).or_insert(0) += 1;
+
+        if let (Some(scan_start_time), Some(points)) =
+            (record.message.scan_start_time(), record.message.layer_point_counts())
+        {
+            self.scans += 1;
+            let scan_start = scan_start_time.unix_timestamp();
+            if let Some(last_scan_start) = self.last_scan_start.insert(record.source.clone(), scan_start) {
+                if scan_start > last_scan_start {
+                    self.scan_periods.add((scan_start - last_scan_start) * 1000.0);
+                }
+            }
+            for (layer, &count) in points.iter().enumerate() {
+                self.layer_points[layer].add(count as f64);
+            }
+        }
+        if let Message::Error(ErrorData {
+            error_register_1,
+            error_register_2,
+            warning_register_1,
+            warning_register_2,
+        }) = record.message
+        {
+            count_bits(&mut self.errors, "error_register_1", error_register_1);
+            count_bits(&mut self.errors, "error_register_2", error_register_2);
+            count_bits(&mut self.warnings, "warning_register_1", warning_register_1);
+            count_bits(&mut self.warnings, "warning_register_2", warning_register_2);
+        }
+        if let Some(object_data) = record.message.object_data() {
+            self.objects_per_frame.add(object_data.objects_vec.len() as f64);
+            for object in &object_data.objects_vec {
+                let track = self
+                    .tracks
+                    .entry((record.source.clone(), object.object_id))
+                    .or_insert((record.timestamp, record.timestamp));
+                if record.timestamp - track.1 > TRACK_TIMEOUT {
+                    self.lifetimes.add(track.1 - track.0);
+                    self.finished_tracks += 1;
+                    *track = (record.timestamp, record.timestamp);
+                }
+                track.1 = record.timestamp;
+            }
+        }
+    }
+
+    pub fn report(self) -> Report {
+        let duration = match (&self.start, &self.last) {
+            (Some(start), Some((end, _))) => end - start.unix_timestamp(),
+            _ => 0.0,
+        };
+        let messages = self
+            .counts
+            .into_iter()
+            .map(|(data_type, count)| {
+                let rate = if duration > 0.0 { count as f64 / duration } else { 0.0 };
+                (data_type, MessageCount { count, rate })
+            })
+            .collect();
+        let (scan_count, layer_points) = (self.scans, self.layer_points);
+        let period = self.scan_periods.summary();
+        let scans = if scan_count > 0 {
+            Some(ScanReport {
+                count: scan_count,
+                frequency: period.as_ref().map(|period| 1000.0 / period.mean),
+                period,
+                points_per_layer: (0..16)
+                    .filter(|&layer| layer_points[layer as usize].max > 0.0)
+                    .filter_map(|layer| layer_points[layer as usize].summary().map(|summary| (layer, summary)))
+                    .collect(),
+            })
+        } else {
+            None
+        };
+        let mut lifetimes = self.lifetimes;
+        for &(first, last) in self.tracks.values() {
+            lifetimes.add(last - first);
+        }
+        let tracks = self.finished_tracks + self.tracks.len() as u64;
+        let objects = self.objects_per_frame.summary().map(|per_frame| ObjectReport {
+            per_frame,
+            tracks,
+            lifetime: lifetimes.summary(),
+        });
+        Report {
+            start_time: self.start.map(|start| start.datetime().to_rfc3339()),
+            end_time: self.last.map(|(_, end)| end.datetime().to_rfc3339()),
+            duration,
+            messages,
+            scans,
+            objects,
+            gap_threshold: self.gap_threshold,
+            gaps: self.gaps,
+            errors: self.errors,
+            warnings: self.warnings,
+        }
+    }
+}
+
+fn write_counts<W: Write>(out: &mut W, title: &str, counts: &BTreeMap<String, u64>) -> io::Result<()> {
+    if counts.is_empty() {
+        return writeln!(out, "{}: none", title);
+    }
+    writeln!(out, "{}:", title)?;
+    for (bit, count) in counts {
+        writeln!(out, "  {:<24} {:>8}", bit, count)?;
+    }
+    Ok(())
+}
+
+impl Report {
+    pub fn write_table<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match (&self.start_time, &self.end_time) {
+            (Some(start), Some(end)) => writeln!(out, "time range: {} to {} ({:.1} s)", start, end, self.duration)?,
+            _ => writeln!(out, "time range: no messages")?,
+        }
+        writeln!(out, "\n{:<24} {:>8} {:>10}", "messages", "count", "rate/s")?;
+        for (data_type, messages) in &self.messages {
+            writeln!(out, "  {:<22} {:>8} {:>10.2}", data_type, messages.count, messages.rate)?;
+        }
+        if let Some(ref scans) = self.scans {
+            writeln!(out, "\nscans: {}", scans.count)?;
+            if let (Some(frequency), Some(ref period)) = (scans.frequency, &scans.period) {
+                writeln!(out, "  frequency: {:.2} Hz", frequency)?;
+                writeln!(
+                    out,
+                    "  period: {:.1} ms (min {:.1}, max {:.1}, jitter {:.2})",
+                    period.mean, period.min, period.max, period.std_dev
+                )?;
+            }
+            writeln!(out, "  {:<22} {:>8} {:>8} {:>8}", "points per scan", "min", "mean", "max")?;
+            for (layer, points) in &scans.points_per_layer {
+                writeln!(out, "    layer {:<14} {:>8} {:>8.1} {:>8}", layer, points.min, points.mean, points.max)?;
+            }
+        }
+        if let Some(ref objects) = self.objects {
+            writeln!(out, "\nobjects:")?;
+            writeln!(
+                out,
+                "  per frame: {:.1} (min {}, max {})",
+                objects.per_frame.mean, objects.per_frame.min, objects.per_frame.max
+            )?;
+            writeln!(out, "  tracks: {}", objects.tracks)?;
+            if let Some(ref lifetime) = objects.lifetime {
+                writeln!(
+                    out,
+                    "  lifetime: {:.2} s (min {:.2}, max {:.2})",
+                    lifetime.mean, lifetime.min, lifetime.max
+                )?;
+            }
+        }
+        writeln!(out, "\ngaps longer than {} s: {}", self.gap_threshold, self.gaps.len())?;
+        for gap in &self.gaps {
+            writeln!(out, "  {} to {} ({:.3} s)", gap.start, gap.end, gap.seconds)?;
+        }
+        writeln!(out)?;
+        write_counts(out, "errors", &self.errors)?;
+        write_counts(out, "warnings", &self.warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+    use fixtures::{object_data, record_at, scan_data};
+    use stream::Record;
+
+    fn scan(source: &str, seconds: f64) -> Record {
+        let mut record = record_at(0x2202, &scan_data(seconds), seconds);
+        record.source = Some(source.to_string());
+        record
+    }
+
+    // a single object with id 1
+    fn objects(source: &str, seconds: f64) -> Record {
+        let mut record = record_at(0x2221, &object_data(&[1]), seconds);
+        record.source = Some(source.to_string());
+        record
+    }
+
+    #[test]
+    fn sources_are_kept_apart() {
+        let mut stats = Stats::new(1.0);
+        for &seconds in [0.0, 0.1, 0.2].iter() {
+            stats.add(&scan("front", seconds));
+            stats.add(&objects("front", seconds));
+            stats.add(&scan("rear", seconds + 0.05));
+            stats.add(&objects("rear", seconds + 0.05));
+        }
+        let report = stats.report();
+        let scans = report.scans.unwrap();
+        assert_eq!(scans.count, 6);
+        let period = scans.period.unwrap();
+        assert!((period.mean - 100.0).abs() < 0.01, "{:?}", period);
+        assert!((period.min - 100.0).abs() < 0.01, "{:?}", period);
+        let objects = report.objects.unwrap();
+        assert_eq!(objects.tracks, 2);
+        assert!((objects.lifetime.unwrap().mean - 0.2).abs() < 0.01);
+    }
+}
@@ -0,0 +1,380 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use serde_json;
+use serde_yaml;
+
+use compression::OutputFile;
+use output::RecordWriter;
+use {DataType, ErrorData, FusedObjectData, FusedScanData, Header, MovementData, NtpTime, ObjectData, Scan, ScalaScanData, ScanData, SensorInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Yaml,
+    Ndjson,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "yaml" | "yml" => Some(Format::Yaml),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension().and_then(|extension| extension.to_str()).and_then(Format::from_name)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Message {
+    ScanData(ScanData),
+    // fused scans and float scans
+    FusedScanData(FusedScanData),
+    ScalaScanData(ScalaScanData),
+    ObjectData(ObjectData),
+    FusedObjectData(FusedObjectData),
+    MovementData(MovementData),
+    Error(ErrorData),
+    SensorInfo(SensorInfo),
+    // data types without a decoder keep their raw payload
+    Payload(Vec<u8>),
+}
+
+impl Message {
+    // the points of any scan data type
+    pub fn scan(&self) -> Option<Scan> {
+        match *self {
+            Message::ScanData(ref scan_data) => Some(scan_data.scan()),
+            Message::FusedScanData(ref scan_data) => Some(scan_data.scan()),
+            Message::ScalaScanData(ref scan_data) => scan_data.scan(),
+            _ => None,
+        }
+    }
+
+    // the start of a scan, without computing its points like `scan()`
+    pub fn scan_start_time(&self) -> Option<&NtpTime> {
+        match *self {
+            Message::ScanData(ref scan_data) => Some(&scan_data.scan_start_time_ntp),
+            Message::FusedScanData(ref scan_data) => Some(&scan_data.scan_start_time),
+            Message::ScalaScanData(ref scan_data) => scan_data.sub_scan_vec.first().map(|sub_scan| &sub_scan.start_time),
+            _ => None,
+        }
+    }
+
+    pub fn point_count(&self) -> Option<usize> {
+        self.layer_point_counts().map(|counts| counts.iter().sum())
+    }
+
+    // points of a scan per layer, layers above 15 count as 15
+    pub fn layer_point_counts(&self) -> Option<[usize; 16]> {
+        let mut counts = [0; 16];
+        match *self {
+            Message::ScanData(ref scan_data) => {
+                for scan_point in &scan_data.scan_point_vec {
+                    counts[scan_point.layer().min(15) as usize] += 1;
+                }
+            },
+            Message::FusedScanData(ref scan_data) => {
+                for scan_point in &scan_data.scan_point_vec {
+                    counts[scan_point.layer.min(15) as usize] += 1;
+                }
+            },
+            Message::ScalaScanData(ref scan_data) if !scan_data.sub_scan_vec.is_empty() => {
+                for scan_point in scan_data.sub_scan_vec.iter().flat_map(|sub_scan| &sub_scan.scan_point_vec) {
+                    counts[scan_point.layer.min(15) as usize] += 1;
+                }
+            },
+            _ => return None,
+        }
+        Some(counts)
+    }
+
+    // lux objects, or those of an ecu in their units
+    pub fn object_data(&self) -> Option<Cow<'_, ObjectData>> {
+        match *self {
+            Message::ObjectData(ref object_data) => Some(Cow::Borrowed(object_data)),
+            Message::FusedObjectData(ref object_data) => Some(Cow::Owned(object_data.object_data())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub data_type: DataType,
+    pub timestamp: f64,
+    // name of the sensor when recording from more than one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub header: Header,
+    pub message: Message,
+}
+
+impl Record {
+    pub fn new(header: Header, payload: &[u8]) -> Record {
+        let message = match header.data_type {
+            DataType::ScanData => ScanData::new(payload).map(Message::ScanData),
+            DataType::ObjectData => ObjectData::new(payload).map(Message::ObjectData),
+            DataType::FusedScanData | DataType::FloatScanData => FusedScanData::new(payload).map(Message::FusedScanData),
+            DataType::ScalaScanData => ScalaScanData::new(payload).map(Message::ScalaScanData),
+            DataType::FusedObjectData => FusedObjectData::new(payload).map(Message::FusedObjectData),
+            DataType::MovementData => MovementData::new(payload).map(Message::MovementData),
+            DataType::Error => ErrorData::new(payload).map(Message::Error),
+            DataType::SensorInfo => SensorInfo::new(payload).map(Message::SensorInfo),
+            _ => None,
+        };
+        // malformed payloads are kept raw
+        let message = message.unwrap_or_else(|| Message::Payload(payload.to_vec()));
+        Record {
+            data_type: header.data_type.clone(),
+            timestamp: header.ntp_time.unix_timestamp(),
+            source: None,
+            header,
+            message,
+        }
+    }
+
+    // tells apart the devices behind a fusion ecu, "device2" or "front/device2"
+    // on the sensor "front"
+    pub fn label_device(&mut self) {
+        let device = format!("device{}", self.header.device_id);
+        self.source = Some(match self.source.take() {
+            Some(source) => format!("{}/{}", source, device),
+            None => device,
+        });
+    }
+}
+
+// `---` separated yaml documents or one json object per line
+pub struct StreamWriter<W: Write> {
+    writer: W,
+    format: Format,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(writer: W, format: Format) -> StreamWriter<W> {
+        StreamWriter {
+            writer,
+            format,
+        }
+    }
+
+}
+
+impl RecordWriter for StreamWriter<OutputFile> {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            Format::Yaml => {
+                let document = serde_yaml::to_string(record)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                self.writer.write_all(b"---\n")?;
+                self.writer.write_all(document.trim_start_matches("---").trim().as_bytes())?;
+                self.writer.write_all(b"\n")
+            },
+            Format::Ndjson => {
+                serde_json::to_writer(&mut self.writer, record)?;
+                self.writer.write_all(b"\n")
+            },
+        }
+    }
+
+    fn flush(&mut self, fsync: bool) -> io::Result<()> {
+        self.writer.sync(fsync)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.writer.finish()
+    }
+}
+
+pub(crate) struct StreamReader<R> {
+    reader: R,
+    format: Format,
+    document: String,
+    // lines read so far and the first line of `document`, for errors
+    line: usize,
+    document_line: usize,
+}
+
+impl<R: BufRead> StreamReader<R> {
+    pub fn new(reader: R, format: Format) -> StreamReader<R> {
+        StreamReader {
+            reader,
+            format,
+            document: String::new(),
+            line: 0,
+            document_line: 0,
+        }
+    }
+
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        let len = self.reader.read_line(line)?;
+        if len > 0 {
+            self.line += 1;
+        }
+        Ok(len)
+    }
+
+    fn next_yaml(&mut self) -> Option<io::Result<Record>> {
+        loop {
+            let mut line = String::new();
+            let len = match self.read_line(&mut line) {
+                Ok(len) => len,
+                Err(e) => return Some(Err(e)),
+            };
+            if len == 0 || line.trim_end() == "---" {
+                if !self.document.trim().is_empty() {
+                    let record = serde_yaml::from_str(&self.document);
+                    self.document.clear();
+                    return match record {
+                        Ok(record) => Some(Ok(record)),
+                        // a truncated last document ends the stream
+                        Err(_) if len == 0 => None,
+                        Err(e) => Some(Err(invalid(self.document_line, e))),
+                    };
+                }
+                if len == 0 {
+                    return None;
+                }
+            } else {
+                if self.document.is_empty() {
+                    self.document_line = self.line;
+                }
+                self.document.push_str(&line);
+            }
+        }
+    }
+
+    fn next_ndjson(&mut self) -> Option<io::Result<Record>> {
+        loop {
+            let mut line = String::new();
+            match self.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {},
+                Err(e) => return Some(Err(e)),
+            }
+            if !line.trim().is_empty() {
+                return match serde_json::from_str(&line) {
+                    Ok(record) => Some(Ok(record)),
+                    // a truncated last line ends the stream
+                    Err(_) if !line.ends_with('\n') => None,
+                    Err(e) => Some(Err(invalid(self.line, e))),
+                };
+            }
+        }
+    }
+}
+
+fn invalid<E: fmt::Display>(line: usize, error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, error))
+}
+
+impl<R: BufRead> Iterator for StreamReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        match self.format {
+            Format::Yaml => self.next_yaml(),
+            Format::Ndjson => self.next_ndjson(),
+        }
+    }
+}
+
+// truncates after the last record that can be parsed
+pub fn repair(data: &mut Vec<u8>, format: Format) -> io::Result<bool> {
+    let mut end = data.len();
+    while end > 0 {
+        let (start, record) = match format {
+            Format::Yaml => {
+                let start = (0..end)
+                    .rev()
+                    .find(|&i| data[i..end].starts_with(b"---\n") && (i == 0 || data[i - 1] == b'\n'))
+                    .unwrap_or(0);
+                (start, serde_yaml::from_slice::<Record>(&data[(start + 4).min(end)..end]).is_ok())
+            },
+            Format::Ndjson => {
+                let line_end = if data[end - 1] == b'\n' { end - 1 } else { end };
+                let start = data[..line_end].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+                (start, serde_json::from_slice::<Record>(&data[start..end]).is_ok())
+            },
+        };
+        if record {
+            break;
+        }
+        end = start;
+    }
+    let mut repaired = end < data.len();
+    data.truncate(end);
+    if end > 0 && data[end - 1] != b'\n' {
+        data.push(b'\n');
+        repaired = true;
+    }
+    Ok(repaired)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{self, Cursor};
+
+    use serde_json;
+
+    use super::{Format, Record, StreamReader, StreamWriter};
+    use compression::{self, OutputFile};
+    use fixtures::{record, temp_path};
+    use output::RecordWriter;
+
+    fn records() -> Vec<Record> {
+        // an error message and a command, which has no decoder
+        let mut records = vec![record(0x2030, &[1, 0, 2, 0, 3, 0, 4, 0]), record(0x2010, &[0x10, 0, 1, 2])];
+        records[1].label_device();
+        records
+    }
+
+    fn round_trip(name: &str, format: Format) {
+        let path = temp_path(name);
+        let records = records();
+        let mut writer = Box::new(StreamWriter::new(OutputFile::create(&path).unwrap(), format));
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap();
+        let read: io::Result<Vec<Record>> = StreamReader::new(compression::open(&path).unwrap(), format).collect();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(serde_json::to_value(read.unwrap()).unwrap(), serde_json::to_value(&records).unwrap());
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        round_trip("round_trip.yaml.zst", Format::Yaml);
+    }
+
+    #[test]
+    fn ndjson_round_trip() {
+        round_trip("round_trip.ndjson.gz", Format::Ndjson);
+    }
+
+    #[test]
+    fn malformed_record() {
+        let line = serde_json::to_string(&records()[0]).unwrap();
+        let data = format!("{}\n{{\"broken\n{}\n", line, line);
+        let mut reader = StreamReader::new(Cursor::new(data), Format::Ndjson);
+        assert!(reader.next().unwrap().is_ok());
+        let error = reader.next().unwrap().unwrap_err();
+        assert!(error.to_string().starts_with("line 2: "), "{}", error);
+    }
+
+    #[test]
+    fn truncated_last_record() {
+        let line = serde_json::to_string(&records()[0]).unwrap();
+        let data = format!("{}\n{}", line, &line[..line.len() / 2]);
+        let read: Vec<_> = StreamReader::new(Cursor::new(data), Format::Ndjson).collect();
+        assert_eq!(read.len(), 1);
+        assert!(read[0].is_ok());
+    }
+}
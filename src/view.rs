@@ -0,0 +1,562 @@
+use std::io::{self, IsTerminal, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use idc::IdcReader;
+use stream::Record;
+use {NtpTime, ObjectData, Scan};
+
+// meters from the center of the view to its top edge
+const DEFAULT_RANGE: f64 = 30.0;
+const MIN_RANGE: f64 = 2.0;
+const MAX_RANGE: f64 = 300.0;
+const ZOOM: f64 = 1.25;
+// velocity arrows show where an object is one second later
+const ARROW_SECONDS: f64 = 1.0;
+const LAYER_COLORS: [Color; 8] = [
+    Color::Blue,
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Red,
+    Color::DarkCyan,
+    Color::DarkGreen,
+];
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+// points in meters, x forward and y to the left as in the scan data
+struct Point {
+    x: f32,
+    y: f32,
+    layer: u8,
+}
+
+#[derive(Clone)]
+struct Object {
+    id: u16,
+    x: f64,
+    y: f64,
+    length: f64,
+    width: f64,
+    orientation: f64,
+    velocity: (f64, f64),
+}
+
+// a scan with the objects known at the time. objects arrive after the scan
+// they were tracked in, so they replace those of the latest scene.
+struct Scene {
+    time: NtpTime,
+    scan_number: Option<u16>,
+    points: Vec<Point>,
+    objects: Vec<Object>,
+}
+
+impl Scene {
+    fn from_scan(scan: &Scan, objects: Vec<Object>) -> Scene {
+        Scene {
+            time: scan.start_time.clone(),
+            scan_number: Some(scan.scan_number),
+            points: scan
+                .points
+                .iter()
+                .map(|point| Point { x: point.x, y: point.y, layer: point.layer })
+                .collect(),
+            objects,
+        }
+    }
+}
+
+fn objects(object_data: &ObjectData) -> Vec<Object> {
+    // object positions, sizes and velocities are in cm
+    object_data
+        .objects_vec
+        .iter()
+        .map(|object_info| Object {
+            id: object_info.object_id,
+            x: (object_info.object_box_center.position_x as f64) / 100.0,
+            y: (object_info.object_box_center.position_y as f64) / 100.0,
+            length: (object_info.object_box_size.size_x as f64) / 100.0,
+            width: (object_info.object_box_size.size_y as f64) / 100.0,
+            orientation: object_info.orientation(),
+            velocity: (
+                (object_info.absolute_velocity.position_x as f64) / 100.0,
+                (object_info.absolute_velocity.position_y as f64) / 100.0,
+            ),
+        })
+        .collect()
+}
+
+// adds the scan or objects of a record to the scenes
+fn add(scenes: &mut Vec<Scene>, record: &Record) {
+    if let Some(scan) = record.message.scan() {
+        let objects = scenes.last().map_or(Vec::new(), |scene| scene.objects.clone());
+        scenes.push(Scene::from_scan(&scan, objects));
+    } else if let Some(object_data) = record.message.object_data() {
+        match scenes.last_mut() {
+            Some(scene) => scene.objects = objects(&object_data),
+            None => scenes.push(Scene {
+                time: object_data.scan_start_timestamp.clone(),
+                scan_number: None,
+                points: Vec::new(),
+                objects: objects(&object_data),
+            }),
+        }
+    }
+}
+
+// one character per cell, drawn in meters
+struct Canvas {
+    width: usize,
+    height: usize,
+    cells: Vec<(char, Color)>,
+    range: f64,
+    center: (f64, f64),
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize, range: f64, center: (f64, f64)) -> Canvas {
+        Canvas {
+            width,
+            height,
+            cells: vec![(' ', Color::Reset); width * height],
+            range,
+            center,
+        }
+    }
+
+    // meters per row, cells are about twice as high as wide
+    fn row_size(&self) -> f64 {
+        self.range / (self.height as f64 / 2.0).max(1.0)
+    }
+
+    // x is up and y is to the left on the screen
+    fn cell(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let row = (self.height as f64 / 2.0 - (x - self.center.0) / self.row_size()).floor();
+        let column = (self.width as f64 / 2.0 - (y - self.center.1) / self.row_size() * 2.0).floor();
+        if row < 0.0 || column < 0.0 || row >= self.height as f64 || column >= self.width as f64 {
+            return None;
+        }
+        Some((column as usize, row as usize))
+    }
+
+    fn set(&mut self, column: usize, row: usize, character: char, color: Color) {
+        if column < self.width && row < self.height {
+            self.cells[row * self.width + column] = (character, color);
+        }
+    }
+
+    fn get(&self, column: usize, row: usize) -> char {
+        self.cells[row * self.width + column].0
+    }
+
+    fn put(&mut self, x: f64, y: f64, character: char, color: Color) {
+        if let Some((column, row)) = self.cell(x, y) {
+            self.set(column, row, character, color);
+        }
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), character: char, color: Color) {
+        let length = (to.0 - from.0).hypot(to.1 - from.1);
+        // half a column per step so that no cell is skipped
+        let steps = (length / (self.row_size() / 4.0)).ceil().max(1.0) as usize;
+        for step in 0..=steps {
+            let t = step as f64 / steps as f64;
+            self.put(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t, character, color);
+        }
+    }
+
+    fn text(&mut self, column: usize, row: usize, text: &str, color: Color) {
+        for (offset, character) in text.chars().enumerate() {
+            self.set(column + offset, row, character, color);
+        }
+    }
+
+    fn draw_points(&mut self, points: &[Point], layers: &[bool; 8]) {
+        for point in points {
+            let layer = point.layer as usize % 8;
+            if !layers[layer] {
+                continue;
+            }
+            if let Some((column, row)) = self.cell(point.x as f64, point.y as f64) {
+                // denser cells get heavier characters
+                let character = match self.get(column, row) {
+                    '.' => ':',
+                    ':' | '*' => '*',
+                    _ => '.',
+                };
+                self.set(column, row, character, LAYER_COLORS[layer]);
+            }
+        }
+    }
+
+    fn draw_object(&mut self, object: &Object) {
+        let (sin, cos) = object.orientation.sin_cos();
+        let corners: Vec<(f64, f64)> = [(1.0, 1.0), (1.0, -1.0), (-1.0, -1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|&(along, across)| {
+                let (dx, dy) = (along * object.length / 2.0, across * object.width / 2.0);
+                (object.x + dx * cos - dy * sin, object.y + dx * sin + dy * cos)
+            })
+            .collect();
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], '+', Color::White);
+        }
+        let (vx, vy) = object.velocity;
+        if vx != 0.0 || vy != 0.0 {
+            let tip = (object.x + vx * ARROW_SECONDS, object.y + vy * ARROW_SECONDS);
+            // screen direction of the arrow, rows grow downwards
+            let (up, left) = (vx / self.row_size(), vy / self.row_size() * 2.0);
+            let shaft = match (left.atan2(up).to_degrees() + 360.0) % 180.0 {
+                angle if !(22.5..157.5).contains(&angle) => '|',
+                angle if angle < 67.5 => '\\',
+                angle if angle < 112.5 => '-',
+                _ => '/',
+            };
+            let head = if up.abs() > left.abs() {
+                if up > 0.0 { '^' } else { 'v' }
+            } else if left > 0.0 {
+                '<'
+            } else {
+                '>'
+            };
+            self.line((object.x, object.y), tip, shaft, Color::Red);
+            self.put(tip.0, tip.1, head, Color::Red);
+        }
+    }
+
+    // ids go next to the box, after everything else so that they stay readable
+    fn draw_label(&mut self, object: &Object) {
+        let half = object.length.max(object.width) / 2.0;
+        if let Some((column, row)) = self.cell(object.x + half, object.y - half) {
+            self.text(column + 1, row, &object.id.to_string(), Color::White);
+        }
+    }
+}
+
+enum Source {
+    Live {
+        address: String,
+        records: Receiver<Record>,
+        closed: bool,
+    },
+    Recording {
+        path: String,
+        // scene time and the instant it was shown while playing
+        playing: Option<(f64, Instant)>,
+    },
+}
+
+struct Viewer {
+    source: Source,
+    scenes: Vec<Scene>,
+    index: usize,
+    paused: bool,
+    range: f64,
+    center: (f64, f64),
+    layers: [bool; 8],
+    show_points: bool,
+    show_objects: bool,
+}
+
+impl Viewer {
+    fn new(source: Source, scenes: Vec<Scene>) -> Viewer {
+        Viewer {
+            source,
+            scenes,
+            index: 0,
+            paused: false,
+            range: DEFAULT_RANGE,
+            // the sensor in the lower half, looking up
+            center: (DEFAULT_RANGE / 2.0, 0.0),
+            layers: [true; 8],
+            show_points: true,
+            show_objects: true,
+        }
+    }
+
+    fn seek(&mut self, index: isize) {
+        let last = self.scenes.len().saturating_sub(1) as isize;
+        self.index = index.max(0).min(last) as usize;
+        if let Source::Recording { ref mut playing, .. } = self.source {
+            if playing.is_some() {
+                *playing = Some((self.scenes[self.index].time.unix_timestamp(), Instant::now()));
+            }
+        }
+    }
+
+    // false to quit
+    fn key(&mut self, key: KeyEvent) -> bool {
+        let step = self.range / 10.0;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.range = (self.range / ZOOM).max(MIN_RANGE),
+            KeyCode::Char('-') => self.range = (self.range * ZOOM).min(MAX_RANGE),
+            KeyCode::Char('w') => self.center.0 += step,
+            KeyCode::Char('s') => self.center.0 -= step,
+            KeyCode::Char('a') => self.center.1 += step,
+            KeyCode::Char('d') => self.center.1 -= step,
+            KeyCode::Char('0') => {
+                self.range = DEFAULT_RANGE;
+                self.center = (DEFAULT_RANGE / 2.0, 0.0);
+            },
+            KeyCode::Char(digit @ '1'..='8') => {
+                let layer = digit as usize - '1' as usize;
+                self.layers[layer] = !self.layers[layer];
+            },
+            KeyCode::Char('p') => self.show_points = !self.show_points,
+            KeyCode::Char('o') => self.show_objects = !self.show_objects,
+            KeyCode::Char(' ') => match self.source {
+                Source::Live { .. } => self.paused = !self.paused,
+                Source::Recording { ref mut playing, .. } => {
+                    *playing = match *playing {
+                        Some(_) => None,
+                        None => Some((self.scenes[self.index].time.unix_timestamp(), Instant::now())),
+                    }
+                },
+            },
+            _ => {
+                if let Source::Recording { .. } = self.source {
+                    let index = self.index as isize;
+                    match key.code {
+                        KeyCode::Left => self.seek(index - 1),
+                        KeyCode::Right => self.seek(index + 1),
+                        KeyCode::PageUp => self.seek(index - 10),
+                        KeyCode::PageDown => self.seek(index + 10),
+                        KeyCode::Home => self.seek(0),
+                        KeyCode::End => self.seek(isize::MAX),
+                        _ => {},
+                    }
+                }
+            },
+        }
+        true
+    }
+
+    // new records of the live stream or the next scene of a playing
+    // recording, true when the view changed
+    fn update(&mut self) -> bool {
+        let mut changed = false;
+        match self.source {
+            Source::Live { ref records, ref mut closed, .. } => loop {
+                match records.try_recv() {
+                    Ok(ref record) if !self.paused => {
+                        add(&mut self.scenes, record);
+                        // only the latest scene is kept
+                        if self.scenes.len() > 1 {
+                            self.scenes.remove(0);
+                        }
+                        changed = true;
+                    },
+                    Ok(_) => {},
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        changed = !*closed;
+                        *closed = true;
+                        break;
+                    },
+                }
+            },
+            Source::Recording { ref mut playing, .. } => {
+                if let Some((start, started)) = *playing {
+                    let now = start + started.elapsed().as_secs_f64();
+                    while self.index + 1 < self.scenes.len() && self.scenes[self.index + 1].time.unix_timestamp() <= now {
+                        self.index += 1;
+                        changed = true;
+                    }
+                    if self.index + 1 == self.scenes.len() {
+                        *playing = None;
+                        changed = true;
+                    }
+                }
+            },
+        }
+        changed
+    }
+
+    fn status(&self) -> String {
+        let scene = self.scenes.get(self.index);
+        let time = scene.map_or("-".to_string(), |scene| {
+            scene.time.datetime().with_timezone(&Local).format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+        });
+        let scan = scene.and_then(|scene| scene.scan_number).map_or("-".to_string(), |number| number.to_string());
+        let contents = scene.map_or(String::new(), |scene| {
+            format!("{} points, {} objects", scene.points.len(), scene.objects.len())
+        });
+        let source = match self.source {
+            Source::Live { ref address, closed, .. } => {
+                let state = if closed {
+                    "connection closed"
+                } else if self.paused {
+                    "paused"
+                } else {
+                    "live"
+                };
+                format!("{} {}", address, state)
+            },
+            Source::Recording { ref path, playing } => format!(
+                "{} {}/{} {}",
+                path,
+                self.index + 1,
+                self.scenes.len(),
+                if playing.is_some() { "playing" } else { "paused" }
+            ),
+        };
+        format!("{}  scan {}  {}  {}  range {:.0} m", source, scan, time, contents, self.range)
+    }
+
+    fn help(&self) -> String {
+        let layers: String = self
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(layer, &shown)| if shown { (b'1' + layer as u8) as char } else { '.' })
+            .collect();
+        let keys = match self.source {
+            Source::Live { .. } => "space pause",
+            Source::Recording { .. } => "space play  left/right step  pgup/pgdn 10 scans  home/end",
+        };
+        format!(
+            "q quit  +/- zoom  wasd pan  0 reset  1-8 layers [{}]  p points {}  o objects {}  {}",
+            layers,
+            if self.show_points { "on" } else { "off" },
+            if self.show_objects { "on" } else { "off" },
+            keys
+        )
+    }
+
+    fn draw<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, (height as usize).saturating_sub(2));
+        let mut canvas = Canvas::new(width, height, self.range, self.center);
+        if let Some(scene) = self.scenes.get(self.index) {
+            if self.show_points {
+                canvas.draw_points(&scene.points, &self.layers);
+            }
+            if self.show_objects {
+                for object in &scene.objects {
+                    canvas.draw_object(object);
+                }
+                for object in &scene.objects {
+                    canvas.draw_label(object);
+                }
+            }
+        }
+        canvas.put(0.0, 0.0, '^', Color::Red);
+        for row in 0..height {
+            queue!(out, MoveTo(0, row as u16))?;
+            let mut color = None;
+            for &(character, cell_color) in &canvas.cells[row * width..(row + 1) * width] {
+                if color != Some(cell_color) {
+                    queue!(out, SetForegroundColor(cell_color))?;
+                    color = Some(cell_color);
+                }
+                queue!(out, Print(character))?;
+            }
+        }
+        queue!(out, ResetColor)?;
+        for (row, line) in [self.status(), self.help()].iter().enumerate() {
+            let line: String = line.chars().take(width).collect();
+            queue!(out, MoveTo(0, (height + row) as u16), Clear(ClearType::CurrentLine), Print(line))?;
+        }
+        out.flush()
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let _terminal = Terminal::enter(&mut out)?;
+        let mut changed = true;
+        loop {
+            if changed {
+                self.draw(&mut out)?;
+            }
+            changed = false;
+            if event::poll(FRAME_INTERVAL)? {
+                match event::read()? {
+                    Event::Key(key) if key.kind != KeyEventKind::Release => {
+                        if !self.key(key) {
+                            return Ok(());
+                        }
+                        changed = true;
+                    },
+                    Event::Resize(_, _) => changed = true,
+                    _ => {},
+                }
+            }
+            changed |= self.update();
+        }
+    }
+}
+
+// raw mode on an alternate screen until dropped, also on errors
+struct Terminal;
+
+impl Terminal {
+    fn enter<W: Write>(out: &mut W) -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        Ok(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn check_terminal() -> io::Result<()> {
+    if io::stdout().is_terminal() {
+        Ok(())
+    } else {
+        Err(io::Error::other("the viewer needs a terminal"))
+    }
+}
+
+// the latest scan and objects of a sensor, received on a thread of their own
+pub fn live(address: &str, stream: TcpStream) -> io::Result<()> {
+    check_terminal()?;
+    let (sender, records) = mpsc::channel();
+    thread::spawn(move || {
+        for (header, payload) in IdcReader::new(stream) {
+            if sender.send(Record::new(header, &payload)).is_err() {
+                break;
+            }
+        }
+    });
+    let source = Source::Live {
+        address: address.to_string(),
+        records,
+        closed: false,
+    };
+    Viewer::new(source, Vec::new()).run()
+}
+
+// all scans of a recording are kept in memory to scrub through them
+pub fn recording<I: Iterator<Item = io::Result<Record>>>(path: &str, records: I) -> io::Result<()> {
+    check_terminal()?;
+    let mut scenes = Vec::new();
+    for record in records {
+        add(&mut scenes, &record?);
+    }
+    if scenes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no scan or object data to view"));
+    }
+    let source = Source::Recording {
+        path: path.to_string(),
+        playing: None,
+    };
+    Viewer::new(source, scenes).run()
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use chrono::prelude::*;
+use serde_json::{self, Value};
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::http::HeaderValue;
+use tungstenite::{self, Message};
+
+use dashboard::Health;
+use foxglove::{self, Channel};
+use stream::Record;
+use {is_interrupted, DataType};
+
+const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+// messages queued for a slow client before newer ones are dropped
+const QUEUE_LENGTH: usize = 64;
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const MESSAGE_DATA: u8 = 0x01;
+
+// published data types, their channel id is the index plus one
+const DATA_TYPES: [DataType; 9] = [
+    DataType::ScanData,
+    DataType::FusedScanData,
+    DataType::ScalaScanData,
+    DataType::FloatScanData,
+    DataType::ObjectData,
+    DataType::FusedObjectData,
+    DataType::MovementData,
+    DataType::Error,
+    DataType::SensorInfo,
+];
+const DIAGNOSTICS_CHANNEL: u32 = DATA_TYPES.len() as u32 + 1;
+
+// channel id, log time in nanoseconds and the json message
+type Outgoing = (u32, u64, Arc<Vec<u8>>);
+
+#[derive(Deserialize)]
+struct Subscription {
+    id: u32,
+    #[serde(rename = "channelId")]
+    channel_id: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum ClientMessage {
+    Subscribe {
+        subscriptions: Vec<Subscription>,
+    },
+    Unsubscribe {
+        #[serde(rename = "subscriptionIds")]
+        subscription_ids: Vec<u32>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+fn channel_id(data_type: &DataType) -> Option<u32> {
+    DATA_TYPES.iter().position(|published| published == data_type).map(|index| index as u32 + 1)
+}
+
+fn advertise() -> Value {
+    let channels = DATA_TYPES
+        .iter()
+        .map(|data_type| (channel_id(data_type).unwrap(), foxglove::channel(data_type)))
+        .chain(Some((DIAGNOSTICS_CHANNEL, foxglove::diagnostics_channel())))
+        .map(|(id, Channel { topic, schema_name, schema })| {
+            json!({
+                "id": id,
+                "topic": topic,
+                "encoding": "json",
+                "schemaName": schema_name,
+                "schema": schema,
+                "schemaEncoding": "jsonschema",
+            })
+        })
+        .collect::<Vec<Value>>();
+    json!({"op": "advertise", "channels": channels})
+}
+
+fn now_nanos() -> u64 {
+    Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64
+}
+
+// the foxglove websocket protocol for viewing a recording live, e.g. in
+// foxglove studio. every client is served on a thread of its own, so a slow
+// one only loses messages and never holds up the recording.
+pub struct Server {
+    clients: Arc<Mutex<Vec<SyncSender<Outgoing>>>>,
+    logs: Receiver<String>,
+}
+
+impl Server {
+    pub fn start(address: &str) -> io::Result<Server> {
+        let listener = TcpListener::bind(address)?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        let (log, logs) = mpsc::channel();
+        let session = now_nanos().to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (sender, messages) = mpsc::sync_channel(QUEUE_LENGTH);
+                accepted.lock().unwrap().push(sender);
+                let session = session.clone();
+                let log = log.clone();
+                thread::spawn(move || serve(stream, messages, &session, &log));
+            }
+        });
+        Ok(Server { clients, logs })
+    }
+
+    // client errors since the last call, for the recorder to print so they
+    // do not write over its status
+    pub fn logs(&self) -> Vec<String> {
+        self.logs.try_iter().collect()
+    }
+
+    // `encode` is only called when a client is connected
+    fn send<F: FnOnce() -> Vec<u8>>(&self, channel: u32, nanos: u64, encode: F) {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.is_empty() {
+            return;
+        }
+        let message = Arc::new(encode());
+        clients.retain(|client| {
+            !matches!(client.try_send((channel, nanos, message.clone())), Err(TrySendError::Disconnected(_)))
+        });
+    }
+
+    pub fn publish(&self, record: &Record) {
+        if let Some(channel) = channel_id(&record.data_type) {
+            self.send(channel, record.header.ntp_time.unix_nanos(), || foxglove::encode(record));
+        }
+    }
+
+    pub fn publish_diagnostics(&self, health: &[Health]) {
+        let nanos = now_nanos();
+        self.send(DIAGNOSTICS_CHANNEL, nanos, || foxglove::diagnostics(health, nanos));
+    }
+}
+
+impl Drop for Server {
+    // lets the clients know that the recording is over
+    fn drop(&mut self) {
+        self.clients.lock().unwrap().clear();
+    }
+}
+
+fn serve(stream: TcpStream, messages: Receiver<Outgoing>, session: &str, log: &Sender<String>) {
+    let client = stream.peer_addr().map(|address| address.to_string()).unwrap_or_default();
+    match serve_client(stream, messages, session) {
+        Ok(()) => {},
+        // a client that simply went away
+        Err(ref e) if matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe
+        ) => {},
+        Err(e) => {
+            let _ = log.send(format!("foxglove client {}: {}", client, e));
+        },
+    }
+}
+
+// accepts the foxglove subprotocol when the client asks for it
+struct Subprotocol;
+
+impl Callback for Subprotocol {
+    fn on_request(self, request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+        let offered = request
+            .headers()
+            .get_all("Sec-WebSocket-Protocol")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.split(',').any(|protocol| protocol.trim() == SUBPROTOCOL));
+        if offered {
+            response.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL));
+        }
+        Ok(response)
+    }
+}
+
+fn io_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+fn serve_client(stream: TcpStream, messages: Receiver<Outgoing>, session: &str) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut websocket = tungstenite::accept_hdr(stream, Subprotocol).map_err(|e| io::Error::other(e.to_string()))?;
+    // short reads so that queued messages go out while waiting for requests
+    websocket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    websocket.get_ref().set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let server_info = json!({
+        "op": "serverInfo",
+        "name": "lidar",
+        "capabilities": [],
+        "supportedEncodings": [],
+        "metadata": {},
+        "sessionId": session,
+    });
+    websocket.send(Message::text(server_info.to_string())).map_err(io_error)?;
+    websocket.send(Message::text(advertise().to_string())).map_err(io_error)?;
+    // subscription id of every subscribed channel
+    let mut subscriptions: HashMap<u32, u32> = HashMap::new();
+    loop {
+        match websocket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(ClientMessage::Subscribe { subscriptions: subscribed }) => {
+                    for subscription in subscribed {
+                        subscriptions.insert(subscription.channel_id, subscription.id);
+                    }
+                },
+                Ok(ClientMessage::Unsubscribe { subscription_ids }) => {
+                    subscriptions.retain(|_, id| !subscription_ids.contains(id));
+                },
+                Ok(ClientMessage::Other) | Err(_) => {},
+            },
+            Ok(_) => {},
+            Err(tungstenite::Error::Io(ref e)) if is_interrupted(e) => {},
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(io_error(e)),
+        }
+        loop {
+            match messages.try_recv() {
+                Ok((channel, nanos, message)) => {
+                    if let Some(&subscription) = subscriptions.get(&channel) {
+                        let mut data = Vec::with_capacity(13 + message.len());
+                        data.push(MESSAGE_DATA);
+                        data.write_u32::<LittleEndian>(subscription)?;
+                        data.write_u64::<LittleEndian>(nanos)?;
+                        data.extend_from_slice(&message);
+                        websocket.write(Message::binary(data)).map_err(io_error)?;
+                    }
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    websocket.close(None).map_err(io_error)?;
+                    return websocket.flush().map_err(io_error);
+                },
+            }
+        }
+        match websocket.flush() {
+            Err(tungstenite::Error::Io(ref e)) if is_interrupted(e) => {},
+            result => result.map_err(io_error)?,
+        }
+    }
+}
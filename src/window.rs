@@ -0,0 +1,166 @@
+use std::time::Instant;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use stream::Record;
+
+// rfc 3339, or a local time of day which means its next occurrence
+pub fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let time = NaiveTime::parse_from_str(text, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+        .ok()?;
+    let now = Local::now();
+    let mut date = now.date_naive();
+    if date.and_time(time) <= now.naive_local() {
+        date += Duration::days(1);
+    }
+    date.and_time(time).and_local_timezone(Local).earliest().map(|time| time.with_timezone(&Utc))
+}
+
+// "500000", "64k", "200M" or "2G"
+pub fn parse_bytes(text: &str) -> Option<u64> {
+    let (number, unit) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1024),
+        'm' | 'M' => (&text[..text.len() - 1], 1024 * 1024),
+        'g' | 'G' => (&text[..text.len() - 1], 1024 * 1024 * 1024),
+        _ => (text, 1),
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+#[derive(Debug, Default)]
+pub struct Window {
+    pub start_at: Option<DateTime<Utc>>,
+    pub start_objects: Option<u16>,
+    pub duration: Option<f64>,
+    pub max_scans: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub until: Option<DateTime<Utc>>,
+    started: Option<Instant>,
+    scans: u64,
+}
+
+impl Window {
+    pub fn is_started(&self) -> bool {
+        self.started.is_some()
+    }
+
+    // false until every start trigger has fired
+    pub fn accept(&mut self, record: &Record) -> bool {
+        if self.started.is_none() {
+            if let Some(start_at) = self.start_at {
+                if Utc::now() < start_at {
+                    return false;
+                }
+            }
+            if let Some(start_objects) = self.start_objects {
+                match record.message.object_data() {
+                    Some(ref object_data) if object_data.number_of_objects >= start_objects => {},
+                    _ => return false,
+                }
+            }
+            self.started = Some(Instant::now());
+        }
+        if record.data_type.is_scan() {
+            self.scans += 1;
+        }
+        true
+    }
+
+    // the reason to stop recording, if any
+    pub fn stop_reason(&self, bytes: u64) -> Option<&'static str> {
+        if let Some(until) = self.until {
+            if Utc::now() >= until {
+                return Some("end time reached");
+            }
+        }
+        let started = self.started?;
+        if let Some(duration) = self.duration {
+            if started.elapsed().as_secs_f64() >= duration {
+                return Some("duration reached");
+            }
+        }
+        if let Some(max_scans) = self.max_scans {
+            if self.scans >= max_scans {
+                return Some("scan count reached");
+            }
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            if bytes >= max_bytes {
+                return Some("size reached");
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use super::{parse_bytes, parse_time, Window};
+    use fixtures::{object_data, record, scan_data};
+
+    #[test]
+    fn bytes() {
+        assert_eq!(parse_bytes("500000"), Some(500000));
+        assert_eq!(parse_bytes("64k"), Some(64 * 1024));
+        assert_eq!(parse_bytes("200M"), Some(200 * 1024 * 1024));
+        assert_eq!(parse_bytes("2g"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_bytes(&u64::MAX.to_string()), Some(u64::MAX));
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        for text in ["", "M", "-1", "1.5G", "200MB", " 200M", "20000000000G"].iter() {
+            assert_eq!(parse_bytes(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn times() {
+        let time = parse_time("2020-06-01T13:33:20+02:00").unwrap();
+        assert_eq!(time, Utc.with_ymd_and_hms(2020, 6, 1, 11, 33, 20).unwrap());
+        // a time of day is in the next 24 hours
+        for text in ["08:00", "23:59:59"].iter() {
+            let seconds = (parse_time(text).unwrap() - Utc::now()).num_seconds();
+            assert!(seconds > 0 && seconds <= 24 * 3600, "{}", text);
+        }
+        assert_eq!(parse_time("25:00"), None);
+        assert_eq!(parse_time("tomorrow"), None);
+    }
+
+    #[test]
+    fn start_objects_and_max_scans() {
+        let mut window = Window {
+            start_objects: Some(2),
+            max_scans: Some(1),
+            ..Default::default()
+        };
+        assert!(!window.accept(&record(0x2202, &scan_data(0.0))));
+        assert!(!window.accept(&record(0x2221, &object_data(&[1]))));
+        assert_eq!(window.stop_reason(0), None);
+        assert!(window.accept(&record(0x2221, &object_data(&[1, 2]))));
+        assert!(window.is_started());
+        assert_eq!(window.stop_reason(0), None);
+        assert!(window.accept(&record(0x2202, &scan_data(0.0))));
+        assert_eq!(window.stop_reason(0), Some("scan count reached"));
+    }
+
+    #[test]
+    fn max_bytes() {
+        let mut window = Window {
+            max_bytes: Some(1000),
+            ..Default::default()
+        };
+        // nothing stops a window that has not started
+        assert_eq!(window.stop_reason(5000), None);
+        assert!(window.accept(&record(0x2030, &[0; 8])));
+        assert_eq!(window.stop_reason(999), None);
+        assert_eq!(window.stop_reason(1000), Some("size reached"));
+    }
+}